@@ -1,32 +1,364 @@
 #![warn(clippy::all)]
 
-use std::path::PathBuf;
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use clap::{Arg, ArgAction, Command};
+use hdrcopier_core::{
+    set_quiet, ColorPrimaries, ColorRange, CopyOptions, MatrixCoefficients, MetadataOverrides,
+    ProgressEvent, Tool, TransferCharacteristics, STATUS_TARGET,
+};
+use log::{Level, LevelFilter, Log, Record};
+
+// The formats which `show` and `convert` can print metadata in
+const FORMATS: [&str; 15] = [
+    "x265",
+    "x264",
+    "svt-av1",
+    "rav1e",
+    "aomenc",
+    "mkvmerge",
+    "tonemap",
+    "mkvtoolnix-gui",
+    "st2086",
+    "env",
+    "numeric",
+    "vapoursynth",
+    "json",
+    "master-display",
+    "all",
+];
+
+// The `show` arguments for parsing output which was already captured from each tool
+const DUMP_ARGS: [(&str, Tool); 3] = [
+    ("from-mkvinfo", Tool::Mkvinfo),
+    ("from-mediainfo", Tool::Mediainfo),
+    ("from-ffprobe", Tool::Ffprobe),
+];
 
 fn main() {
     let args = Command::new("hdrcopier")
         .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::new("quiet")
+                .help("Only show warnings and errors")
+                .long("quiet")
+                .short('q')
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .global(true),
+        )
+        .arg(
+            Arg::new("verbose")
+                .help("Also show which tool each value was read with")
+                .long("verbose")
+                .short('v')
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("tool-timeout")
+                .help(
+                    "Give up on a tool reading metadata if it runs for longer than this many \
+                     seconds; 0 means no limit",
+                )
+                .long("tool-timeout")
+                .value_name("SECONDS")
+                .default_value("30")
+                .value_parser(clap::value_parser!(u64))
+                .global(true),
+        )
         .subcommand(
             Command::new("copy")
                 .about("Merges the metadata from one file with the media streams from another")
                 .arg(
                     Arg::new("input")
-                        .help("file to copy metadata from")
+                        .help(
+                            "file to copy metadata from, or a directory to copy from each file \
+                             to the target file with the same name. With --from-json, metadata \
+                             which was saved with `show --format json`",
+                        )
                         .required(true)
                         .index(1),
                 )
                 .arg(
                     Arg::new("target")
-                        .help("file to copy metadata to; must be a matroska file")
+                        .help(
+                            "file to copy metadata to; must be a matroska or webm file. If the input is \
+                             a directory, this must be too",
+                        )
                         .required(true)
                         .index(2),
                 )
+                .arg(
+                    Arg::new("continue-on-error")
+                        .help(
+                            "When copying between directories, keep going after a file fails \
+                             instead of stopping",
+                        )
+                        .long("continue-on-error")
+                        .action(ArgAction::SetTrue),
+                )
                 .arg(
                     Arg::new("chapters")
                         .help("Also copy chapters from input to output")
                         .long("chapters")
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("merge-chapters")
+                        .help(
+                            "Merge chapters from input with the chapters already on the target, \
+                             instead of replacing them",
+                        )
+                        .long("merge-chapters")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("wait")
+                        .help(
+                            "Wait up to this many seconds for the target to stop changing size \
+                             before editing it, e.g. if it is still being written by an encoder. \
+                             0 doesn't wait at all",
+                        )
+                        .long("wait")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("from-bitstream")
+                        .help(
+                            "Ignore the metadata in the container headers and only read \
+                             what is in the video stream",
+                        )
+                        .long("from-bitstream")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("from-json")
+                        .help(
+                            "Read the input as metadata which was saved with `show --format \
+                             json`, so the original file isn't needed",
+                        )
+                        .long("from-json")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all([
+                            "from-bitstream",
+                            "source-track",
+                            "chapters",
+                            "merge-chapters",
+                            "dolby-vision",
+                            "hdr10plus",
+                        ]),
+                )
+                .arg(
+                    Arg::new("plan")
+                        .help(
+                            "Show which properties would be changed on the target, without \
+                             changing anything",
+                        )
+                        .long("plan")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("primaries")
+                        .help("Use this colour primaries code instead of the input's")
+                        .long("primaries")
+                        .value_name("CODE")
+                        .value_parser(clap::value_parser!(u8)),
+                )
+                .arg(
+                    Arg::new("transfer")
+                        .help("Use this transfer characteristics code instead of the input's")
+                        .long("transfer")
+                        .value_name("CODE")
+                        .value_parser(clap::value_parser!(u8)),
+                )
+                .arg(
+                    Arg::new("matrix")
+                        .help("Use this matrix coefficients code instead of the input's")
+                        .long("matrix")
+                        .value_name("CODE")
+                        .value_parser(clap::value_parser!(u8)),
+                )
+                .arg(
+                    Arg::new("range")
+                        .help("Use this colour range instead of the input's; 0 is full, 1 is limited")
+                        .long("range")
+                        .value_name("CODE")
+                        .value_parser(clap::value_parser!(u8)),
+                )
+                .arg(
+                    Arg::new("max-cll")
+                        .help("Use these content light levels instead of the input's")
+                        .long("max-cll")
+                        .value_name("MAX_CLL,MAX_FALL")
+                        .value_parser(parse_max_cll),
+                )
+                .arg(
+                    Arg::new("master-display")
+                        .help(
+                            "Use this mastering display instead of the input's, in x265's format, \
+                             e.g. G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)",
+                        )
+                        .long("master-display")
+                        .value_name("STRING"),
+                )
+                .arg(
+                    Arg::new("max-luminance")
+                        .help(
+                            "Use this maximum mastering display luminance in cd/m² instead of \
+                             the input's, keeping its primaries and white point",
+                        )
+                        .long("max-luminance")
+                        .value_name("NITS")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("min-luminance")
+                        .help(
+                            "Use this minimum mastering display luminance in cd/m² instead of \
+                             the input's, keeping its primaries and white point",
+                        )
+                        .long("min-luminance")
+                        .value_name("NITS")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("track")
+                        .help(
+                            "Which video track of the target to copy metadata to, counting \
+                             only video tracks and starting from 1",
+                        )
+                        .long("track")
+                        .value_name("N")
+                        .default_value("1")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("source-track")
+                        .help(
+                            "Which video track of the input to copy metadata from, counting \
+                             only video tracks and starting from 1",
+                        )
+                        .long("source-track")
+                        .value_name("N")
+                        .default_value("1")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("basic-only")
+                        .help(
+                            "Only copy the colour range, primaries, transfer and matrix, \
+                             skipping the mastering display and content light levels",
+                        )
+                        .long("basic-only")
+                        .alias("no-hdr")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("hdr-only"),
+                )
+                .arg(
+                    Arg::new("hdr-only")
+                        .help(
+                            "Only copy the mastering display and content light levels, \
+                             skipping the colour range, primaries, transfer and matrix",
+                        )
+                        .long("hdr-only")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("field-order")
+                        .help("Also copy whether the video is interlaced, and its field order")
+                        .long("field-order")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("copy-title")
+                        .help("Also copy the file's title and the video track's name")
+                        .long("copy-title")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("dolby-vision")
+                        .help(
+                            "Also copy Dolby Vision metadata, by injecting the input's RPU into \
+                             the target's video stream; requires dovi_tool and ffmpeg",
+                        )
+                        .long("dolby-vision")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("hdr10plus")
+                        .help(
+                            "Also copy HDR10+ metadata, by injecting the input's dynamic metadata \
+                             into the target's video stream; requires hdr10plus_tool and ffmpeg",
+                        )
+                        .long("hdr10plus")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .help(
+                            "Parse the target again afterwards, and fail if any of the metadata \
+                             was not applied",
+                        )
+                        .long("verify")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .help(
+                            "Edit the target even if no colour metadata was found in the input. \
+                             This is dangerous, since it's usually because parsing failed",
+                        )
+                        .long("force")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(yes_arg())
+                .arg(
+                    Arg::new("json")
+                        .help(
+                            "Print a JSON summary of the metadata which was written, instead of \
+                             the progress messages",
+                        )
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["plan", "dry-run"]),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Print the command which would be run, without running it")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help(
+                            "Write the result to a new file with mkvmerge, instead of editing \
+                             the target in place",
+                        )
+                        .long("output")
+                        .short('o')
+                        .value_name("PATH"),
+                )
+                .arg(
+                    Arg::new("mkvpropedit-args")
+                        .help(
+                            "Additional arguments to pass directly to mkvpropedit, after `--`. \
+                             These are passed to mkvmerge instead when using `--output`. \
+                             These are not validated in any way; use at your own risk",
+                        )
+                        .index(3)
+                        .num_args(0..)
+                        .allow_hyphen_values(true)
+                        .last(true),
                 ),
         )
         .subcommand(
@@ -35,6 +367,62 @@ fn main() {
                 .arg(
                     Arg::new("input")
                         .help("file to parse metadata from")
+                        .required_unless_present_any(DUMP_ARGS.map(|(name, _)| name))
+                        .conflicts_with_all(DUMP_ARGS.map(|(name, _)| name))
+                        .index(1),
+                )
+                .args(DUMP_ARGS.map(|(name, tool)| {
+                    Arg::new(name)
+                        .help(format!(
+                            "Parse output which was already captured from {}, instead of \
+                             running anything; `-` reads it from stdin",
+                            tool.name()
+                        ))
+                        .long(name)
+                        .value_name("FILE")
+                        .conflicts_with_all(
+                            DUMP_ARGS
+                                .map(|(other, _)| other)
+                                .into_iter()
+                                .filter(|&other| other != name)
+                                .chain(["from-bitstream"]),
+                        )
+                }))
+                .arg(
+                    Arg::new("format")
+                        .help("display output in a CLI-compatible format")
+                        .long("format")
+                        .short('f')
+                        .value_parser(FORMATS),
+                )
+                .arg(
+                    Arg::new("from-bitstream")
+                        .help(
+                            "Ignore the metadata in the container headers and only read \
+                             what is in the video stream",
+                        )
+                        .long("from-bitstream")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("source-track")
+                        .help(
+                            "Which video track of the input to show, counting only video \
+                             tracks and starting from 1. ffprobe's captured output only has \
+                             the track it was run on, so this is ignored for it",
+                        )
+                        .long("source-track")
+                        .value_name("N")
+                        .default_value("1")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                ),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Displays metadata saved by `show --format json` in another format")
+                .arg(
+                    Arg::new("input")
+                        .help("JSON file to read metadata from")
                         .required(true)
                         .index(1),
                 )
@@ -43,11 +431,98 @@ fn main() {
                         .help("display output in a CLI-compatible format")
                         .long("format")
                         .short('f')
-                        .value_parser(["x265", "svt-av1", "rav1e", "mkvmerge"]),
+                        .value_parser(FORMATS),
+                ),
+        )
+        .subcommand(
+            Command::new("renormalize")
+                .about(
+                    "Strips all colour metadata from a file and writes it back in its canonical \
+                     location",
+                )
+                .arg(
+                    Arg::new("target")
+                        .help("file to renormalize; must be a matroska or webm file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(yes_arg()),
+        )
+        .subcommand(
+            Command::new("strip")
+                .about("Removes all colour metadata from a file's container headers")
+                .arg(
+                    Arg::new("target")
+                        .help("file to strip; must be a matroska or webm file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("track")
+                        .help(
+                            "Which video track of the target to strip, counting only video \
+                             tracks and starting from 1",
+                        )
+                        .long("track")
+                        .value_name("N")
+                        .default_value("1")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Print the command which would be run, without running it")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(yes_arg()),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about(
+                    "Compares the metadata of two files, exiting with an error if any of it differs",
+                )
+                .arg(
+                    Arg::new("source")
+                        .help("file to compare against")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("target")
+                        .help("file to compare")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about(
+                    "Checks a file for inconsistent colour metadata, exiting with an error if \
+                     any is found",
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("file to check")
+                        .required(true)
+                        .index(1),
                 ),
         )
         .get_matches();
 
+    // The logger can only fail to be set if one already was
+    let _ = log::set_logger(&StderrLogger);
+    if args.get_flag("quiet") {
+        set_quiet(true);
+        log::set_max_level(LevelFilter::Warn);
+    } else if args.get_flag("verbose") {
+        log::set_max_level(LevelFilter::Debug);
+    } else {
+        log::set_max_level(LevelFilter::Info);
+    }
+    if let Some(&timeout) = args.get_one::<u64>("tool-timeout") {
+        hdrcopier_core::set_tool_timeout(Duration::from_secs(timeout));
+    }
+
     match args.subcommand_name() {
         Some("copy") => {
             let sub_args = args.subcommand_matches("copy").unwrap();
@@ -57,20 +532,374 @@ fn main() {
                     .get_one::<String>("target")
                     .expect("Value required"),
             );
-            let chapters = sub_args.get_flag("chapters");
+            let options = CopyOptions {
+                chapters: sub_args.get_flag("chapters"),
+                merge_chapters: sub_args.get_flag("merge-chapters"),
+                wait: sub_args.get_one::<u64>("wait").copied(),
+                from_bitstream: sub_args.get_flag("from-bitstream"),
+                from_json: sub_args.get_flag("from-json"),
+                extra_args: sub_args
+                    .get_many::<String>("mkvpropedit-args")
+                    .map(|args| args.cloned().collect())
+                    .unwrap_or_default(),
+                plan: sub_args.get_flag("plan"),
+                output: sub_args.get_one::<String>("output").map(PathBuf::from),
+                dry_run: sub_args.get_flag("dry-run"),
+                verify: sub_args.get_flag("verify"),
+                track: sub_args.get_one::<u64>("track").copied(),
+                source_track: sub_args.get_one::<u64>("source-track").copied(),
+                overrides: MetadataOverrides {
+                    primaries: sub_args
+                        .get_one::<u8>("primaries")
+                        .map(|&value| ColorPrimaries::from_u8(value)),
+                    transfer: sub_args
+                        .get_one::<u8>("transfer")
+                        .map(|&value| TransferCharacteristics::from_u8(value)),
+                    matrix: sub_args
+                        .get_one::<u8>("matrix")
+                        .map(|&value| MatrixCoefficients::from_u8(value)),
+                    range: sub_args
+                        .get_one::<u8>("range")
+                        .map(|&value| ColorRange::from_u8(value)),
+                    max_cll: sub_args.get_one::<(u32, u32)>("max-cll").copied(),
+                    master_display: sub_args.get_one::<String>("master-display").cloned(),
+                    max_luma: sub_args.get_one::<f64>("max-luminance").copied(),
+                    min_luma: sub_args.get_one::<f64>("min-luminance").copied(),
+                },
+                dolby_vision: sub_args.get_flag("dolby-vision"),
+                hdr10plus: sub_args.get_flag("hdr10plus"),
+                basic_only: sub_args.get_flag("basic-only"),
+                hdr_only: sub_args.get_flag("hdr-only"),
+                field_order: sub_args.get_flag("field-order"),
+                force: sub_args.get_flag("force"),
+                copy_title: sub_args.get_flag("copy-title"),
+            };
 
-            hdrcopier_core::copy(input, target, chapters)
+            // mkvpropedit changes the target permanently, so make sure it's the right one
+            let in_place = options.output.is_none() && !options.plan && !options.dry_run;
+            exit_if_missing(&input, "Input");
+            // With `--wait`, the target may not have been created yet
+            if options.wait.unwrap_or(0) == 0 {
+                exit_if_missing(&target, "Target");
+            }
+            if in_place && !sub_args.get_flag("yes") {
+                let what = if target.is_dir() {
+                    format!("the files in {:?}", target)
+                } else {
+                    format!("{:?}", target)
+                };
+                confirm_in_place(&what, args.get_flag("quiet"));
+            }
+
+            let json = sub_args.get_flag("json");
+            if input.is_dir() || target.is_dir() {
+                if json {
+                    eprintln!("`--json` cannot be used when copying between directories");
+                    exit(1);
+                }
+                copy_dirs(
+                    &input,
+                    &target,
+                    &options,
+                    sub_args.get_flag("continue-on-error"),
+                );
+                return;
+            }
+            if !json {
+                exit_on_error(hdrcopier_core::copy(
+                    input,
+                    target,
+                    &options,
+                    &print_progress,
+                ));
+                return;
+            }
+            set_quiet(true);
+            log::set_max_level(LevelFilter::Warn);
+            // `--json` can't be combined with `--plan` or `--dry-run`, so something was written
+            let report = exit_on_error(hdrcopier_core::copy(input, target, &options, &|_| ()))
+                .expect("Metadata was written");
+            println!("{}", exit_on_error(report.to_json()));
         }
         Some("show") => {
             let sub_args = args.subcommand_matches("show").unwrap();
+            let format: Option<&String> = sub_args.get_one("format");
+            let track = *sub_args
+                .get_one::<u64>("source-track")
+                .expect("Has default");
+            let dump = DUMP_ARGS.into_iter().find_map(|(name, tool)| {
+                sub_args
+                    .get_one::<String>(name)
+                    .map(|path| (tool, exit_on_error(read_dump(path))))
+            });
+            let found = if let Some((tool, output)) = dump {
+                exit_on_error(hdrcopier_core::show_tool_output(
+                    tool,
+                    &output,
+                    format.map(|s| s.as_str()),
+                    track,
+                ))
+            } else {
+                let input =
+                    PathBuf::from(sub_args.get_one::<String>("input").expect("Value required"));
+                let from_bitstream = sub_args.get_flag("from-bitstream");
+                exit_on_error(hdrcopier_core::show(
+                    input,
+                    format.map(|s| s.as_str()),
+                    from_bitstream,
+                    track,
+                ))
+            };
+            // A separate code from errors, so scripts can tell the two apart
+            if !found {
+                exit(2);
+            }
+        }
+        Some("convert") => {
+            let sub_args = args.subcommand_matches("convert").unwrap();
             let input = PathBuf::from(sub_args.get_one::<String>("input").expect("Value required"));
 
             let format: Option<&String> = sub_args.get_one("format");
-            hdrcopier_core::show(input, format.map(|s| s.as_str()))
+            exit_on_error(hdrcopier_core::convert(input, format.map(|s| s.as_str())));
+        }
+        Some("renormalize") => {
+            let sub_args = args.subcommand_matches("renormalize").unwrap();
+            let target = PathBuf::from(
+                sub_args
+                    .get_one::<String>("target")
+                    .expect("Value required"),
+            );
+
+            exit_if_missing(&target, "Target");
+            if !sub_args.get_flag("yes") {
+                confirm_in_place(&format!("{:?}", target), args.get_flag("quiet"));
+            }
+            exit_on_error(hdrcopier_core::renormalize(target));
+            log::info!("Done!");
+        }
+        Some("strip") => {
+            let sub_args = args.subcommand_matches("strip").unwrap();
+            let target = PathBuf::from(
+                sub_args
+                    .get_one::<String>("target")
+                    .expect("Value required"),
+            );
+            let track = *sub_args.get_one::<u64>("track").expect("Has default");
+            let dry_run = sub_args.get_flag("dry-run");
+
+            exit_if_missing(&target, "Target");
+            if !dry_run && !sub_args.get_flag("yes") {
+                confirm_in_place(&format!("{:?}", target), args.get_flag("quiet"));
+            }
+            exit_on_error(hdrcopier_core::strip(target, track, dry_run));
+            if !dry_run {
+                log::info!("Done!");
+            }
+        }
+        Some("diff") => {
+            let sub_args = args.subcommand_matches("diff").unwrap();
+            let source = PathBuf::from(
+                sub_args
+                    .get_one::<String>("source")
+                    .expect("Value required"),
+            );
+            let target = PathBuf::from(
+                sub_args
+                    .get_one::<String>("target")
+                    .expect("Value required"),
+            );
+
+            if !exit_on_error(hdrcopier_core::diff(source, target)) {
+                exit(1);
+            }
+        }
+        Some("validate") => {
+            let sub_args = args.subcommand_matches("validate").unwrap();
+            let input = PathBuf::from(sub_args.get_one::<String>("input").expect("Value required"));
+
+            let warnings = exit_on_error(hdrcopier_core::validate(input));
+            if warnings.is_empty() {
+                log::info!("No problems found");
+                return;
+            }
+            for warning in &warnings {
+                println!("{}", warning);
+            }
+            exit(1);
         }
         _ => {
             eprintln!("Unrecognized command entered; see `hdrcopier -h` for usage");
-            std::process::exit(1);
+            exit(1);
+        }
+    }
+}
+
+fn copy_dirs(source_dir: &Path, target_dir: &Path, options: &CopyOptions, continue_on_error: bool) {
+    if !source_dir.is_dir() || !target_dir.is_dir() {
+        eprintln!("Input and target must both be directories, or both be files");
+        exit(1);
+    }
+    if options.output.is_some() {
+        eprintln!("`--output` cannot be used when copying between directories");
+        exit(1);
+    }
+    if options.from_json {
+        eprintln!("`--from-json` cannot be used when copying between directories");
+        exit(1);
+    }
+
+    let pairs = exit_on_error(hdrcopier_core::pair_files(source_dir, target_dir));
+    let mut succeeded = 0;
+    let mut failed = Vec::new();
+    for (source, target) in pairs {
+        log::info!("Copying {:?} to {:?}", source, target);
+        if let Err(e) = hdrcopier_core::copy(source, target.clone(), options, &print_progress) {
+            log::error!("Failed: {}", e);
+            failed.push(target);
+            if !continue_on_error {
+                break;
+            }
+        } else {
+            succeeded += 1;
+        }
+    }
+
+    log::info!("{} succeeded, {} failed", succeeded, failed.len());
+    for target in &failed {
+        log::info!("    {}", target.display());
+    }
+    if !failed.is_empty() {
+        exit(1);
+    }
+}
+
+// Reads captured tool output from a file, or from stdin if the path is `-`
+fn read_dump(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut output = String::new();
+        io::stdin().read_to_string(&mut output)?;
+        return Ok(output);
+    }
+    fs::read_to_string(path)
+}
+
+// Shared by the subcommands which edit their target in place
+fn yes_arg() -> Arg {
+    Arg::new("yes")
+        .help(
+            "Edit the target in place without asking first. This is required \
+             when stdin isn't a terminal, or with --quiet",
+        )
+        .long("yes")
+        .short('y')
+        .alias("overwrite")
+        .action(ArgAction::SetTrue)
+}
+
+// Asks on stderr before a target is edited in place, like `rm -i`, and exits unless the
+// answer is yes. Without a terminal, or with `--quiet`, there is nobody to ask,
+// so `--yes` has to be passed instead.
+// Checked before asking for confirmation, so that a mistyped path
+// fails straight away rather than after the user has said yes
+fn exit_if_missing(path: &Path, what: &str) {
+    if !path.exists() {
+        eprintln!("{} {:?} does not exist", what, path);
+        exit(1);
+    }
+}
+
+fn confirm_in_place(what: &str, quiet: bool) {
+    if quiet || !io::stdin().is_terminal() {
+        eprintln!(
+            "Refusing to edit {} in place without confirmation; pass --yes to allow it",
+            what
+        );
+        exit(1);
+    }
+    eprint!("Edit {} in place? [y/N] ", what);
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err()
+        || !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    {
+        eprintln!("Aborted");
+        exit(1);
+    }
+}
+
+fn exit_on_error<T, E: Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn parse_max_cll(value: &str) -> Result<(u32, u32), String> {
+    let (max_cll, max_fall) = value
+        .split_once(',')
+        .ok_or_else(|| "expected two values separated by a comma".to_string())?;
+    Ok((
+        max_cll.trim().parse().map_err(|e| format!("{}", e))?,
+        max_fall.trim().parse().map_err(|e| format!("{}", e))?,
+    ))
+}
+
+fn print_progress(event: ProgressEvent) {
+    match event {
+        ProgressEvent::WaitingForTarget => log::info!("Waiting for target to finish writing..."),
+        ProgressEvent::ParsingMetadata => log::info!("Parsing metadata..."),
+        ProgressEvent::ExtractingChapters => log::info!("Extracting chapters..."),
+        ProgressEvent::MergingChapters => log::info!("Merging chapters..."),
+        ProgressEvent::CopyingDolbyVision => log::info!("Copying Dolby Vision metadata..."),
+        ProgressEvent::CopyingHdr10Plus => log::info!("Copying HDR10+ metadata..."),
+        // mkvpropedit's own output is shown while applying
+        ProgressEvent::Applying => (),
+        ProgressEvent::Verifying => log::info!("Verifying metadata..."),
+        ProgressEvent::Done => log::info!("Done!"),
+    }
+}
+
+// Prints messages from hdrcopier-core and the CLI to stderr, in the same
+// format that was used before they went through `log`
+struct StderrLogger;
+
+// Whether a status line is on screen, and needs to be cleared before anything else is printed
+static STATUS_SHOWN: AtomicBool = AtomicBool::new(false);
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // The status is only useful while waiting, so it's overwritten in place,
+        // and left out entirely when stderr is going somewhere other than a terminal
+        if record.target() == STATUS_TARGET {
+            if !io::stderr().is_terminal() {
+                return;
+            }
+            let status = record.args().to_string();
+            let shown = !status.is_empty();
+            if shown || STATUS_SHOWN.load(Ordering::Relaxed) {
+                eprint!("\r\x1b[K{}", status);
+            }
+            STATUS_SHOWN.store(shown, Ordering::Relaxed);
+            return;
+        }
+        if STATUS_SHOWN.swap(false, Ordering::Relaxed) {
+            eprint!("\r\x1b[K");
+        }
+        match record.level() {
+            Level::Error => eprintln!("Error: {}", record.args()),
+            Level::Warn => eprintln!("Warning: {}", record.args()),
+            _ => eprintln!("{}", record.args()),
         }
     }
+
+    fn flush(&self) {}
 }
@@ -0,0 +1,235 @@
+use std::{collections::HashSet, fs, ops::Range, path::Path};
+
+use anyhow::Result;
+use roxmltree::{Document, Node, ParsingOptions};
+use tempfile::TempPath;
+
+use crate::metadata::temp_path;
+
+// Combines the chapters already present on the target with the chapters
+// from the source, in the XML format produced by mkvextract:
+//
+// <Chapters>
+//   <EditionEntry>
+//     <EditionFlagDefault>1</EditionFlagDefault>
+//     <EditionUID>1234</EditionUID>
+//     <ChapterAtom>
+//       <ChapterUID>5678</ChapterUID>
+//       ...
+//     </ChapterAtom>
+//   </EditionEntry>
+// </Chapters>
+//
+// The target's editions are kept as-is and come first, followed by the
+// source's editions in their original order. Any UIDs from the source
+// which collide with the target's are removed, so that mkvpropedit
+// generates fresh ones instead of producing a file with duplicate UIDs.
+// Only one edition may be the default, so if the target already has one,
+// the source's editions lose their default flag.
+//
+// The merged chapters are written to a temporary file, which is removed when dropped.
+pub fn merge_chapters(target: &Path, source: &Path) -> Result<TempPath> {
+    let merged = merge_chapters_xml(&fs::read_to_string(target)?, &fs::read_to_string(source)?)?;
+    let output = temp_path("hdrcp_merged_chapters", ".xml")?;
+    fs::write(&output, merged)?;
+    Ok(output)
+}
+
+// Both documents are parsed to find the editions, but the source's editions
+// are copied over as text, so that everything in them is kept exactly as it was
+fn merge_chapters_xml(target_xml: &str, source_xml: &str) -> Result<String> {
+    let target_doc = parse_chapters(target_xml, "target")?;
+    let source_doc = parse_chapters(source_xml, "source")?;
+    let target_root = target_doc.root_element();
+
+    let used_uids: HashSet<&str> = target_root
+        .descendants()
+        .filter(|node| is_element(*node, "EditionUID") || is_element(*node, "ChapterUID"))
+        .map(element_text)
+        .collect();
+    let target_has_default = editions(target_root).any(is_default_edition);
+    // After the target's last edition, or at the end of an empty `<Chapters>`
+    let insert_pos = match editions(target_root).last() {
+        Some(edition) => edition.range().end,
+        None => target_xml[..target_root.range().end]
+            .rfind("</")
+            .filter(|&pos| pos > target_root.range().start)
+            .ok_or_else(|| anyhow::anyhow!("Failed to find chapters in target"))?,
+    };
+
+    let mut merged = String::with_capacity(target_xml.len() + source_xml.len());
+    merged.push_str(&target_xml[..insert_pos]);
+    for edition in editions(source_doc.root_element()) {
+        let mut edits = Vec::new();
+        for node in edition.descendants() {
+            if (is_element(node, "EditionUID") || is_element(node, "ChapterUID"))
+                && used_uids.contains(element_text(node))
+            {
+                edits.push((with_indentation(source_xml, node.range()), ""));
+            }
+        }
+        if target_has_default && is_default_edition(edition) {
+            for flag in edition
+                .children()
+                .filter(|node| is_element(*node, "EditionFlagDefault"))
+            {
+                if let Some(text) = flag.first_child().filter(Node::is_text) {
+                    edits.push((text.range(), "0"));
+                }
+            }
+        }
+        edits.sort_by_key(|(range, _)| range.start);
+
+        let range = edition.range();
+        let mut copied = range.start;
+        merged.push_str("\n  ");
+        for (edit, replacement) in edits {
+            merged.push_str(&source_xml[copied..edit.start]);
+            merged.push_str(replacement);
+            copied = edit.end;
+        }
+        merged.push_str(&source_xml[copied..range.end]);
+    }
+    merged.push_str(&target_xml[insert_pos..]);
+    Ok(merged)
+}
+
+fn parse_chapters<'a>(xml: &'a str, file: &str) -> Result<Document<'a>> {
+    // mkvextract writes a DOCTYPE, which roxmltree rejects unless told otherwise
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let doc = Document::parse_with_options(xml, options)
+        .map_err(|e| anyhow::anyhow!("Failed to parse chapters in {}: {}", file, e))?;
+    if !is_element(doc.root_element(), "Chapters") {
+        anyhow::bail!("Failed to find chapters in {}", file);
+    }
+    Ok(doc)
+}
+
+fn editions<'a, 'input>(chapters: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    chapters
+        .children()
+        .filter(|node| is_element(*node, "EditionEntry"))
+}
+
+fn is_default_edition(edition: Node) -> bool {
+    edition
+        .children()
+        .any(|node| is_element(node, "EditionFlagDefault") && element_text(node) == "1")
+}
+
+fn is_element(node: Node, tag: &str) -> bool {
+    node.is_element() && node.tag_name().name() == tag
+}
+
+fn element_text<'a>(node: Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or_default().trim()
+}
+
+// Extends the range of an element to include the indentation preceding it,
+// so that removing it doesn't leave a blank line behind
+fn with_indentation(xml: &str, range: Range<usize>) -> Range<usize> {
+    let line_start = xml[..range.start]
+        .rfind('\n')
+        .filter(|&nl| xml[nl..range.start].trim().is_empty())
+        .unwrap_or(range.start);
+    line_start..range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE Chapters SYSTEM "matroskachapters.dtd">
+<Chapters>
+  <EditionEntry>
+    <EditionFlagDefault>1</EditionFlagDefault>
+    <EditionUID>100</EditionUID>
+    <ChapterAtom>
+      <ChapterUID>200</ChapterUID>
+      <ChapterTimeStart>00:00:00.000000000</ChapterTimeStart>
+    </ChapterAtom>
+  </EditionEntry>
+</Chapters>
+"#;
+
+    // Whitespace inside the tags, and an edition whose UIDs collide with the target's
+    const SOURCE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Chapters>
+  <EditionEntry >
+    <EditionFlagDefault>
+      1
+    </EditionFlagDefault >
+    <EditionUID>100</EditionUID>
+    <ChapterAtom>
+      <ChapterUID> 200 </ChapterUID>
+      <ChapterUID>201</ChapterUID>
+      <ChapterDisplay><ChapString>Intro &amp; recap</ChapString></ChapterDisplay>
+    </ChapterAtom>
+  </EditionEntry>
+  <EditionEntry>
+    <EditionUID>300</EditionUID>
+  </EditionEntry>
+</Chapters>
+"#;
+
+    fn uids(doc: &Document, tag: &str) -> Vec<String> {
+        doc.descendants()
+            .filter(|node| is_element(*node, tag))
+            .map(|node| element_text(node).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn merges_editions_after_the_targets() {
+        let merged = merge_chapters_xml(TARGET, SOURCE).unwrap();
+        let doc = parse_chapters(&merged, "merged").unwrap();
+        let editions: Vec<_> = editions(doc.root_element()).collect();
+        assert_eq!(editions.len(), 3);
+        assert_eq!(uids(&doc, "EditionUID"), ["100", "300"]);
+        assert_eq!(uids(&doc, "ChapterUID"), ["200", "201"]);
+        assert_eq!(
+            editions
+                .iter()
+                .map(|e| is_default_edition(*e))
+                .collect::<Vec<_>>(),
+            [true, false, false]
+        );
+        assert!(merged.contains("<ChapString>Intro &amp; recap</ChapString>"));
+        assert!(merged.starts_with(&TARGET[..TARGET.find("  </EditionEntry>").unwrap()]));
+    }
+
+    #[test]
+    fn keeps_source_default_without_target_default() {
+        let target = TARGET.replace(
+            "<EditionFlagDefault>1</EditionFlagDefault>",
+            "<EditionFlagDefault>0</EditionFlagDefault>",
+        );
+        let merged = merge_chapters_xml(&target, SOURCE).unwrap();
+        let doc = parse_chapters(&merged, "merged").unwrap();
+        assert_eq!(
+            editions(doc.root_element())
+                .map(is_default_edition)
+                .collect::<Vec<_>>(),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    fn merges_into_empty_chapters() {
+        let merged = merge_chapters_xml("<Chapters>\n</Chapters>\n", SOURCE).unwrap();
+        let doc = parse_chapters(&merged, "merged").unwrap();
+        assert_eq!(editions(doc.root_element()).count(), 2);
+        assert_eq!(uids(&doc, "EditionUID"), ["100", "300"]);
+    }
+
+    #[test]
+    fn rejects_invalid_chapters() {
+        assert!(merge_chapters_xml("<Chapters>", SOURCE).is_err());
+        assert!(merge_chapters_xml("<Tags></Tags>", SOURCE).is_err());
+        assert!(merge_chapters_xml("<Chapters/>", SOURCE).is_err());
+    }
+}
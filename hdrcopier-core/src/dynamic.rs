@@ -0,0 +1,213 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+use tempfile::TempPath;
+
+use crate::{
+    metadata::{find_video_track, temp_path, video_track_info},
+    parse::ToolOutput,
+    tools::{tool_command, ToolCommand},
+};
+
+// Dynamic HDR metadata lives in the video stream itself rather than the
+// container, so each kind needs its own tool to move it between streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicMetadata {
+    DolbyVision,
+    Hdr10Plus,
+}
+
+impl DynamicMetadata {
+    pub fn name(self) -> &'static str {
+        match self {
+            DynamicMetadata::DolbyVision => "Dolby Vision",
+            DynamicMetadata::Hdr10Plus => "HDR10+",
+        }
+    }
+
+    pub fn tool(self) -> &'static str {
+        match self {
+            DynamicMetadata::DolbyVision => "dovi_tool",
+            DynamicMetadata::Hdr10Plus => "hdr10plus_tool",
+        }
+    }
+
+    // Dolby Vision is stored as a binary RPU, and HDR10+ as JSON
+    fn suffix(self) -> &'static str {
+        match self {
+            DynamicMetadata::DolbyVision => ".bin",
+            DynamicMetadata::Hdr10Plus => ".json",
+        }
+    }
+
+    // Reads a raw HEVC stream from stdin
+    fn extract_command(self, metadata: &Path) -> Command {
+        let mut command = tool_command(self.tool());
+        match self {
+            DynamicMetadata::DolbyVision => command.arg("extract-rpu"),
+            DynamicMetadata::Hdr10Plus => command.arg("extract"),
+        };
+        command.arg("-").arg("-o").arg(metadata);
+        command
+    }
+
+    fn inject_command(self, video: &Path, metadata: &Path, output: &Path) -> Command {
+        let mut command = tool_command(self.tool());
+        match self {
+            DynamicMetadata::DolbyVision => command.arg("inject-rpu").arg("--rpu-in"),
+            DynamicMetadata::Hdr10Plus => command.arg("inject").arg("-j"),
+        };
+        command
+            .arg(metadata)
+            .arg("-i")
+            .arg(video)
+            .arg("-o")
+            .arg(output);
+        command
+    }
+}
+
+// Copies dynamic metadata from the given video track of the input, counting from 1,
+// into the given video track of the target, writing the result to `output`, which may be
+// the target itself. This has to rewrite the video stream, so it works by:
+//
+// 1. Extracting the metadata from the input
+// 2. Extracting the target's video stream with mkvextract
+// 3. Injecting the metadata into that stream
+// 4. Muxing the new stream back together with the rest of the target's tracks
+//
+// The new stream becomes the first video track of the output.
+// The tools only support HEVC, so neither file can be AV1.
+//
+// The intermediate files are temporary, and are removed once we're done whether
+// or not copying succeeds. Returns false without changing anything if the tool
+// is not installed.
+pub fn copy_dynamic_metadata(
+    kind: DynamicMetadata,
+    input: &Path,
+    source_track: u64,
+    target: &Path,
+    track: u64,
+    output: &Path,
+) -> Result<bool> {
+    if tool_command(kind.tool()).arg("--version").output().is_err() {
+        log::warn!(
+            "{} was not found, so {} metadata will not be copied",
+            kind.tool(),
+            kind.name()
+        );
+        return Ok(false);
+    }
+
+    let metadata = temp_path("hdrcp_metadata", kind.suffix())?;
+    let video = temp_path("hdrcp_video", ".hevc")?;
+    let injected = temp_path("hdrcp_injected", ".hevc")?;
+    // This is renamed over the output at the end, so it has to be on the same filesystem
+    let remuxed = remuxed_path(output)?;
+    inject(
+        kind,
+        input,
+        source_track,
+        target,
+        track,
+        &metadata,
+        &video,
+        &injected,
+        &remuxed,
+    )?;
+    remuxed.persist(output)?;
+
+    ToolOutput::global().invalidate(output);
+    Ok(true)
+}
+
+fn remuxed_path(output: &Path) -> Result<TempPath> {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(tempfile::Builder::new()
+        .prefix("hdrcp_injected")
+        .suffix(".mkv")
+        .tempfile_in(dir)?
+        .into_temp_path())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn inject(
+    kind: DynamicMetadata,
+    input: &Path,
+    source_track: u64,
+    target: &Path,
+    track: u64,
+    metadata: &Path,
+    video: &Path,
+    injected: &Path,
+    remuxed: &Path,
+) -> Result<()> {
+    // The tools need a raw HEVC stream, which ffmpeg can extract from any container
+    let mut ffmpeg = tool_command("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(input)
+        // ffmpeg counts the video streams from 0
+        .arg("-map")
+        .arg(format!("0:v:{}", source_track - 1))
+        .args(["-c:v", "copy", "-bsf:v", "hevc_mp4toannexb"])
+        .args(["-f", "hevc", "-"])
+        .stdout(Stdio::piped())
+        .tool_spawn()?;
+    let status = kind
+        .extract_command(metadata)
+        .stdin(ffmpeg.stdout.take().expect("stdout is piped"))
+        .tool_run()?;
+    if !ffmpeg.wait()?.success() || !status.success() {
+        anyhow::bail!(
+            "Failed to extract {} metadata from {:?}",
+            kind.name(),
+            input
+        );
+    }
+
+    let track_info = video_track_info(target, track)?;
+    let track_id = find_video_track(target, track)?;
+    let status = tool_command("mkvextract")
+        .arg(target)
+        .arg("tracks")
+        .arg(format!("{}:{}", track_id, video.display()))
+        .tool_run()?;
+    if !status.success() {
+        anyhow::bail!("Failed to extract video track {} from {:?}", track, target);
+    }
+
+    let status = kind.inject_command(video, metadata, injected).tool_run()?;
+    if !status.success() {
+        anyhow::bail!(
+            "Failed to inject {} metadata into {:?}",
+            kind.name(),
+            target
+        );
+    }
+
+    // A raw stream has no timestamps, so mkvmerge needs to be told the frame rate
+    let mut command = tool_command("mkvmerge");
+    command.arg("-o").arg(remuxed);
+    if let Some(duration) = track_info["properties"]["default_duration"].as_u64() {
+        command
+            .arg("--default-duration")
+            .arg(format!("0:{}ns", duration));
+    }
+    command
+        .arg(injected)
+        .arg("-d")
+        .arg(format!("!{}", track_id))
+        .arg(target);
+    let status = command.tool_run()?;
+    // mkvmerge exits with 1 for warnings, which still produces a usable file
+    if !matches!(status.code(), Some(0) | Some(1)) {
+        anyhow::bail!("Failed to remux {:?} with the new video stream", target);
+    }
+    Ok(())
+}
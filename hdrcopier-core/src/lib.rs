@@ -1,55 +1,606 @@
 #![warn(clippy::all)]
 
+mod chapters;
+mod dynamic;
+mod matroska;
 mod metadata;
 mod parse;
+mod tools;
+mod validate;
 mod values;
 
-use std::{path::PathBuf, process::exit};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::metadata::{extract_chapters, Metadata};
+use anyhow::Result;
+use serde::Serialize;
 
-pub fn copy(input: PathBuf, target: PathBuf, chapters: bool) {
+use crate::{
+    chapters::merge_chapters,
+    dynamic::{copy_dynamic_metadata, DynamicMetadata},
+    matroska::read_doc_type,
+    metadata::{extract_chapters, validate_overrides},
+    validate::validate_metadata,
+};
+pub use crate::{
+    metadata::{
+        BasicMetadata, ChromaSiting, ColorCoordinates, ColorFormat, DoviMetadata, HdrFormat,
+        HdrMetadata, Metadata, WhitePoint,
+    },
+    parse::Tool,
+    tools::{set_quiet, set_tool_timeout, STATUS_TARGET},
+    validate::ValidationWarning,
+    values::{
+        ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics, UnknownValueError,
+    },
+};
+
+// Options for how `copy` should behave. The defaults match running
+// `hdrcopier copy` with no flags.
+#[derive(Default)]
+pub struct CopyOptions {
+    // Copy chapters from the input, replacing any on the target
+    pub chapters: bool,
+    // Copy chapters from the input, keeping any on the target
+    pub merge_chapters: bool,
+    // Wait up to this many seconds for the target to stop being written to.
+    // Zero means not to wait, the same as `None`.
+    pub wait: Option<u64>,
+    // Ignore the container headers of the input when parsing
+    pub from_bitstream: bool,
+    // The input is metadata which was saved with `show --format json`, rather than a video
+    pub from_json: bool,
+    // Arguments passed through to mkvpropedit as-is
+    pub extra_args: Vec<String>,
+    // Print what would change on the target instead of changing it
+    pub plan: bool,
+    // Write the result to this file with mkvmerge, instead of editing the target in place
+    pub output: Option<PathBuf>,
+    // Print the command which would be run instead of running it
+    pub dry_run: bool,
+    // Parse the written file afterwards to check that the metadata was applied
+    pub verify: bool,
+    // Which video track of the target to edit, starting from 1. Defaults to the first.
+    pub track: Option<u64>,
+    // Which video track of the input to read, likewise
+    pub source_track: Option<u64>,
+    // Values to use instead of the ones parsed from the input
+    pub overrides: MetadataOverrides,
+    // Copy the Dolby Vision RPU into the target's video stream, using dovi_tool
+    pub dolby_vision: bool,
+    // Copy the HDR10+ metadata into the target's video stream, using hdr10plus_tool
+    pub hdr10plus: bool,
+    // Skip the mastering display and content light levels, e.g. for an SDR target
+    pub basic_only: bool,
+    // Skip the colour range, primaries, transfer and matrix
+    pub hdr_only: bool,
+    // Also copy whether the video is interlaced, and its field order
+    pub field_order: bool,
+    // Run mkvpropedit even if no metadata was found in the input
+    pub force: bool,
+    // Also copy the segment title and the video track's name
+    pub copy_title: bool,
+}
+
+// Values which replace the ones parsed from the input, for fixing mistagged sources.
+// Any which are `None` are left as parsed.
+#[derive(Default)]
+pub struct MetadataOverrides {
+    pub primaries: Option<ColorPrimaries>,
+    pub transfer: Option<TransferCharacteristics>,
+    pub matrix: Option<MatrixCoefficients>,
+    pub range: Option<ColorRange>,
+    // Maximum content light level, and maximum frame-average light level
+    pub max_cll: Option<(u32, u32)>,
+    // In the format used by x265, e.g.
+    // G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)
+    pub master_display: Option<String>,
+    // Mastering display luminances in cd/m², which replace the ones from the input
+    // or from `master_display`, leaving the primaries and white point as they are
+    pub max_luma: Option<f64>,
+    pub min_luma: Option<f64>,
+}
+
+// Reports which phase a long-running operation is in,
+// so that a frontend can display its progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    WaitingForTarget,
+    ParsingMetadata,
+    ExtractingChapters,
+    MergingChapters,
+    CopyingDolbyVision,
+    CopyingHdr10Plus,
+    Applying,
+    Verifying,
+    Done,
+}
+
+// What `copy` wrote, for frontends which want to report it
+#[derive(Serialize)]
+pub struct CopyReport {
+    pub input: PathBuf,
+    // The file which was written, which is the target unless `output` was given
+    pub output: PathBuf,
+    pub metadata: Metadata,
+    pub chapters: bool,
+    pub dolby_vision: bool,
+    pub hdr10plus: bool,
+    // The mkvpropedit or mkvmerge command which applied the metadata
+    pub command: String,
+}
+
+impl CopyReport {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// Parses all of the colour metadata we can find in the input
+pub fn parse_metadata(input: &Path) -> Result<Metadata> {
+    if !input.is_file() {
+        anyhow::bail!("Input file {:?} does not exist", input);
+    }
+
+    Metadata::parse(input, false, 1)
+}
+
+// Returns what was written, or `None` if nothing was, because of `plan` or `dry_run`
+pub fn copy(
+    input: PathBuf,
+    target: PathBuf,
+    options: &CopyOptions,
+    progress: &dyn Fn(ProgressEvent),
+) -> Result<Option<CopyReport>> {
     if !input.is_file() {
-        eprintln!("Input file {:?} does not exist", input);
-        exit(1);
+        anyhow::bail!("Input file {:?} does not exist", input);
+    }
+    if options.track == Some(0) || options.source_track == Some(0) {
+        anyhow::bail!("Track numbers start from 1");
+    }
+    if options.output.as_ref() == Some(&target) {
+        anyhow::bail!("Output file must be different from the target file");
+    }
+    if options.basic_only && options.hdr_only {
+        anyhow::bail!("Only one of basic-only and HDR-only can be used");
+    }
+    validate_overrides(&options.overrides)?;
+    // Everything else needs the original file
+    if options.from_json {
+        if options.from_bitstream || options.source_track.unwrap_or(1) != 1 {
+            anyhow::bail!("Saved metadata has no container headers or other tracks to read");
+        }
+        if options.chapters || options.merge_chapters {
+            anyhow::bail!("Chapters can't be copied from saved metadata");
+        }
+        if options.dolby_vision || options.hdr10plus {
+            anyhow::bail!("Dynamic HDR metadata can't be copied from saved metadata");
+        }
+    }
+    // The target may not even exist until whatever is writing it gets going,
+    // so nothing about it can be checked before this
+    if let Some(wait) = options.wait.filter(|&wait| wait > 0) {
+        progress(ProgressEvent::WaitingForTarget);
+        if !wait_for_stable_size(&target, Duration::from_secs(wait)) {
+            log::warn!(
+                "Target file {:?} was still changing after {} seconds",
+                target,
+                wait
+            );
+        }
     }
     if !target.is_file() {
-        eprintln!("Target file {:?} does not exist", target);
-        exit(1);
+        anyhow::bail!("Target file {:?} does not exist", target);
     }
+    // Editing in place needs mkvpropedit, which only handles Matroska and WebM,
+    // while a new output can be remuxed from anything mkvmerge can read
+    let webm = match options.output {
+        Some(ref output) => output
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("webm")),
+        None => match read_doc_type(&target)?.as_deref() {
+            Some("matroska") => false,
+            Some("webm") => true,
+            _ => anyhow::bail!("Target file {:?} is not a Matroska or WebM file", target),
+        },
+    };
 
-    let metadata = match Metadata::parse(&input) {
-        Ok(metadata) => metadata,
-        Err(e) => {
-            eprintln!("{}", e);
-            exit(1);
-        }
+    progress(ProgressEvent::ParsingMetadata);
+    let mut metadata = if options.from_json {
+        read_saved_metadata(&input)?
+    } else {
+        Metadata::parse(
+            &input,
+            options.from_bitstream,
+            options.source_track.unwrap_or(1),
+        )?
     };
-    let chapters = if chapters {
+    metadata.apply_overrides(&options.overrides)?;
+    if options.basic_only {
+        metadata.hdr = None;
+    }
+    if options.hdr_only {
+        metadata.basic = None;
+        metadata.chroma_siting = None;
+        metadata.color_format = None;
+    }
+    if !options.field_order {
+        metadata.field_order = None;
+    }
+    if !options.copy_title {
+        metadata.title = None;
+        metadata.track_name = None;
+    }
+    // WebM only allows a subset of Matroska's elements
+    if webm && metadata.field_order.take().is_some() {
+        log::warn!("WebM doesn't allow a field order, so it will not be copied");
+    }
+    print_warnings(&metadata);
+    if options.plan {
+        // Only the container headers are edited, so those are what the plan compares against
+        let existing = Metadata::parse_container(&target, options.track.unwrap_or(1))?;
+        metadata.print_plan(&existing);
+        return Ok(None);
+    }
+    // Finding nothing usually means parsing went wrong, e.g. a tool is missing,
+    // so this is treated as an error rather than editing the target anyway
+    let copies_dynamic = (options.dolby_vision && metadata.dolby_vision.is_some())
+        || (options.hdr10plus && metadata.hdr10plus);
+    let copies_chapters = options.chapters || options.merge_chapters;
+    let copies_title = metadata.title.is_some() || metadata.track_name.is_some();
+    if metadata.basic.is_none()
+        && metadata.hdr.is_none()
+        && !copies_dynamic
+        && !copies_chapters
+        && !copies_title
+        && options.extra_args.is_empty()
+        && !options.force
+    {
+        anyhow::bail!(
+            "No colour metadata was found in {:?}; pass --force to edit the target anyway",
+            input
+        );
+    }
+    // The chapters files are temporary, and are removed once we're done
+    // whether or not copying succeeds
+    let chapters = if options.merge_chapters {
+        progress(ProgressEvent::ExtractingChapters);
+        match (extract_chapters(&input), extract_chapters(&target)) {
+            (Some(source), Some(existing)) => {
+                progress(ProgressEvent::MergingChapters);
+                Some(merge_chapters(&existing, &source)?)
+            }
+            (source, _) => source,
+        }
+    } else if options.chapters {
+        progress(ProgressEvent::ExtractingChapters);
         extract_chapters(&input)
     } else {
         None
     };
-    if let Err(e) = metadata.apply(&target, chapters.as_deref()) {
-        eprintln!("{}", e);
-        exit(1);
-    };
+    let mut track = options.track.unwrap_or(1);
+    let mut edit_target = target.clone();
+    let mut output = options.output.clone();
+    let mut copied_dynamic = Vec::new();
+    let dynamic = [
+        (
+            DynamicMetadata::DolbyVision,
+            options.dolby_vision,
+            metadata.dolby_vision.is_some(),
+            ProgressEvent::CopyingDolbyVision,
+        ),
+        (
+            DynamicMetadata::Hdr10Plus,
+            options.hdr10plus,
+            metadata.hdr10plus,
+            ProgressEvent::CopyingHdr10Plus,
+        ),
+    ];
+    for (kind, requested, present, event) in dynamic {
+        if !requested {
+            continue;
+        }
+        if !present {
+            log::warn!("Input has no {} metadata to copy", kind.name());
+            continue;
+        }
+        if webm {
+            log::warn!(
+                "{} metadata can't be copied into WebM, which doesn't allow HEVC",
+                kind.name()
+            );
+            continue;
+        }
+        if options.dry_run {
+            log::info!(
+                "{} metadata would be copied into the target with {}",
+                kind.name(),
+                kind.tool()
+            );
+            continue;
+        }
+        progress(event);
+        let destination = output.clone().unwrap_or_else(|| edit_target.clone());
+        if copy_dynamic_metadata(
+            kind,
+            &input,
+            options.source_track.unwrap_or(1),
+            &edit_target,
+            track,
+            &destination,
+        )? {
+            // The rewritten video stream is now the first video track of the
+            // destination, so the rest of the metadata can be applied in place
+            edit_target = destination;
+            output = None;
+            track = 1;
+            copied_dynamic.push(kind);
+        }
+    }
+    progress(ProgressEvent::Applying);
+    let command = match output {
+        Some(ref output) => metadata.remux(
+            &edit_target,
+            track,
+            output,
+            chapters.as_deref(),
+            &options.extra_args,
+            options.dry_run,
+        ),
+        None => metadata.apply(
+            &edit_target,
+            track,
+            chapters.as_deref(),
+            false,
+            &options.extra_args,
+            options.dry_run,
+        ),
+    }?;
+    if options.dry_run {
+        return Ok(None);
+    }
+    let written = options.output.clone().unwrap_or(target);
+    if options.verify {
+        progress(ProgressEvent::Verifying);
+        let actual = Metadata::parse(&written, false, track)?;
+        let unapplied = metadata.unapplied_fields(&actual);
+        if !unapplied.is_empty() {
+            anyhow::bail!(
+                "Some metadata was not applied to {:?}: {}",
+                written,
+                unapplied.join(", ")
+            );
+        }
+    }
+
+    progress(ProgressEvent::Done);
+    Ok(Some(CopyReport {
+        input,
+        output: written,
+        chapters: chapters.is_some(),
+        dolby_vision: copied_dynamic.contains(&DynamicMetadata::DolbyVision),
+        hdr10plus: copied_dynamic.contains(&DynamicMetadata::Hdr10Plus),
+        metadata,
+        command,
+    }))
+}
+
+// Returns whether any metadata was found, after printing it either way.
+// `track` is which video track of the input to show, starting from 1.
+pub fn show(
+    input: PathBuf,
+    formatting: Option<&str>,
+    from_bitstream: bool,
+    track: u64,
+) -> Result<bool> {
+    if !input.is_file() {
+        anyhow::bail!("Input file {:?} does not exist", input);
+    }
+    if track == 0 {
+        anyhow::bail!("Track numbers start from 1");
+    }
 
-    eprintln!("Done!");
+    let metadata = Metadata::parse(&input, from_bitstream, track)?;
+    print_warnings(&metadata);
+    metadata.print(formatting)?;
+    Ok(!metadata.is_empty())
 }
 
-pub fn show(input: PathBuf, formatting: Option<&str>) {
+// Like `show`, but parses output which was already captured from one of the tools,
+// e.g. on another machine, instead of running anything
+pub fn show_tool_output(
+    tool: Tool,
+    output: &str,
+    formatting: Option<&str>,
+    track: u64,
+) -> Result<bool> {
+    if track == 0 {
+        anyhow::bail!("Track numbers start from 1");
+    }
+    let metadata = tool.parse(output, track)?;
+    print_warnings(&metadata);
+    metadata.print(formatting)?;
+    Ok(!metadata.is_empty())
+}
+
+// Prints metadata which was saved with `show --format json`,
+// so that the original file doesn't need to be kept around
+pub fn convert(input: PathBuf, formatting: Option<&str>) -> Result<()> {
     if !input.is_file() {
-        eprintln!("Input file {:?} does not exist", input);
-        exit(1);
+        anyhow::bail!("Input file {:?} does not exist", input);
     }
 
-    let metadata = match Metadata::parse(&input) {
-        Ok(metadata) => metadata,
-        Err(e) => {
-            eprintln!("{}", e);
-            exit(1);
+    read_saved_metadata(&input)?.print(formatting)
+}
+
+fn read_saved_metadata(input: &Path) -> Result<Metadata> {
+    fs::read_to_string(input)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        .map_err(|e| anyhow::anyhow!("Failed to read metadata from {:?}: {}", input, e))
+}
+
+// Pairs each Matroska or WebM file in `target_dir` with the file in `source_dir` which has
+// the same name apart from its extension, e.g. for copying a whole season at once.
+// Targets without a matching source are skipped with a warning.
+pub fn pair_files(source_dir: &Path, target_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    for dir in [source_dir, target_dir] {
+        if !dir.is_dir() {
+            anyhow::bail!("Directory {:?} does not exist", dir);
         }
-    };
-    metadata.print(formatting);
+    }
+
+    let mut sources: Vec<PathBuf> = list_files(source_dir)?;
+    sources.sort();
+    let mut targets: Vec<PathBuf> = list_files(target_dir)?
+        .into_iter()
+        .filter(|path| {
+            path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("mkv") || ext.eq_ignore_ascii_case("webm")
+            })
+        })
+        .collect();
+    targets.sort();
+
+    let mut pairs = Vec::new();
+    for target in targets {
+        let matching: Vec<&PathBuf> = sources
+            .iter()
+            .filter(|source| *source != &target && source.file_stem() == target.file_stem())
+            .collect();
+        match matching.as_slice() {
+            [] => log::warn!("No source file found for {:?}", target),
+            [source] => pairs.push(((*source).clone(), target)),
+            [source, ..] => {
+                log::warn!(
+                    "Multiple source files found for {:?}, using {:?}",
+                    target,
+                    source
+                );
+                pairs.push(((*source).clone(), target));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// Prints the metadata of both files side by side. Returns whether all of it matches.
+pub fn diff(source: PathBuf, target: PathBuf) -> Result<bool> {
+    for path in [&source, &target] {
+        if !path.is_file() {
+            anyhow::bail!("File {:?} does not exist", path);
+        }
+    }
+
+    let source_metadata = Metadata::parse(&source, false, 1)?;
+    let target_metadata = Metadata::parse(&target, false, 1)?;
+    Ok(source_metadata.print_diff(&target_metadata))
+}
+
+pub fn validate(input: PathBuf) -> Result<Vec<ValidationWarning>> {
+    let metadata = parse_metadata(&input)?;
+    Ok(validate_metadata(&metadata))
+}
+
+fn print_warnings(metadata: &Metadata) {
+    if metadata.dolby_vision.is_some() {
+        log::warn!(
+            "This file contains Dolby Vision dynamic metadata, which hdrcopier only \
+             copies with `--dolby-vision`."
+        );
+    }
+    if metadata.hdr10plus {
+        log::warn!(
+            "This file contains HDR10+ dynamic metadata, which hdrcopier only copies \
+             with `--hdr10plus`."
+        );
+    }
+
+    // Theatrical masters use the DCI white point, but consumer HDR10 should
+    // always be D65, so this is usually a sign of mislabeled content.
+    let is_pq = metadata
+        .basic
+        .as_ref()
+        .is_some_and(|basic| basic.transfer == Some(TransferCharacteristics::Pq));
+    let white_point = metadata
+        .hdr
+        .as_ref()
+        .and_then(|hdr| hdr.color_coords.as_ref())
+        .map(|coords| coords.white_point());
+    if let Some(white_point) = white_point {
+        if is_pq && white_point != WhitePoint::D65 {
+            log::warn!(
+                "Mastering display uses a {} white point instead of D65, which is \
+                 unusual for HDR10 content",
+                white_point.name()
+            );
+        }
+    }
+}
+
+// The target may still be in the process of being written by an encoder or muxer,
+// in which case mkvpropedit will fail. There's no portable way to know if another
+// process has the file open, so instead wait until the size stops changing.
+fn wait_for_stable_size(target: &Path, timeout: Duration) -> bool {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    let start = Instant::now();
+    let mut last_size = None;
+    loop {
+        let size = fs::metadata(target).map(|meta| meta.len()).ok();
+        if size.is_some() && size == last_size {
+            return true;
+        }
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        last_size = size;
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Removes all of the colour metadata from the target's container headers,
+// for files which were mistagged as HDR
+pub fn strip(target: PathBuf, track: u64, dry_run: bool) -> Result<()> {
+    if !target.is_file() {
+        anyhow::bail!("Target file {:?} does not exist", target);
+    }
+    if track == 0 {
+        anyhow::bail!("Track numbers start from 1");
+    }
+
+    let mut existing = Metadata::parse_container(&target, track)?;
+    // Interlacing isn't colour metadata, and removing it would break playback
+    existing.field_order = None;
+    if !existing.strip(&target, track, dry_run)? {
+        log::warn!("{:?} has no colour metadata to strip", target);
+    }
+    Ok(())
+}
+
+pub fn renormalize(target: PathBuf) -> Result<()> {
+    if !target.is_file() {
+        anyhow::bail!("Target file {:?} does not exist", target);
+    }
+
+    // Only what's already in the container headers is rewritten, so that nothing which
+    // mediainfo or ffprobe find in the video stream is added
+    let metadata = Metadata::parse_container(&target, 1)?;
+    metadata.apply(&target, 1, None, true, &[], false)?;
+    Ok(())
 }
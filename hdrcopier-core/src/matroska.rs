@@ -0,0 +1,470 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::Result;
+
+use crate::{
+    metadata::{BasicMetadata, ChromaSiting, ColorCoordinates, ColorFormat, HdrMetadata, Metadata},
+    values::{ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics},
+};
+
+// Element IDs, from https://www.matroska.org/technical/elements.html
+const EBML_HEADER: u32 = 0x1A45_DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_A966;
+const TITLE: u32 = 0x7BA9;
+const CLUSTER: u32 = 0x1F43_B675;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_TYPE: u32 = 0x83;
+const NAME: u32 = 0x536E;
+const VIDEO: u32 = 0xE0;
+const FLAG_INTERLACED: u32 = 0x9A;
+const FIELD_ORDER: u32 = 0x9D;
+const COLOUR: u32 = 0x55B0;
+const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+const BITS_PER_CHANNEL: u32 = 0x55B2;
+const CHROMA_SUBSAMPLING_HORZ: u32 = 0x55B3;
+const CHROMA_SUBSAMPLING_VERT: u32 = 0x55B4;
+const CB_SUBSAMPLING_HORZ: u32 = 0x55B5;
+const CB_SUBSAMPLING_VERT: u32 = 0x55B6;
+const RANGE: u32 = 0x55B9;
+const TRANSFER_CHARACTERISTICS: u32 = 0x55BA;
+const PRIMARIES: u32 = 0x55BB;
+const CHROMA_SITING_HORZ: u32 = 0x55B7;
+const CHROMA_SITING_VERT: u32 = 0x55B8;
+const MAX_CLL: u32 = 0x55BC;
+const MAX_FALL: u32 = 0x55BD;
+const MASTERING_METADATA: u32 = 0x55D0;
+const PRIMARY_R_CHROMATICITY_X: u32 = 0x55D1;
+const PRIMARY_R_CHROMATICITY_Y: u32 = 0x55D2;
+const PRIMARY_G_CHROMATICITY_X: u32 = 0x55D3;
+const PRIMARY_G_CHROMATICITY_Y: u32 = 0x55D4;
+const PRIMARY_B_CHROMATICITY_X: u32 = 0x55D5;
+const PRIMARY_B_CHROMATICITY_Y: u32 = 0x55D6;
+const WHITE_POINT_CHROMATICITY_X: u32 = 0x55D7;
+const WHITE_POINT_CHROMATICITY_Y: u32 = 0x55D8;
+const LUMINANCE_MAX: u32 = 0x55D9;
+const LUMINANCE_MIN: u32 = 0x55DA;
+
+// The Tracks element is normally a few kilobytes, so anything
+// much larger than this means the file is corrupt
+const MAX_TRACKS_SIZE: u64 = 16 * 1024 * 1024;
+
+// Reads the DocType from the EBML header, which is "matroska" or "webm" for the files
+// mkvpropedit can edit. A header without one is treated as Matroska.
+// Returns `None` if the file isn't EBML at all.
+pub fn read_doc_type(input: &Path) -> Result<Option<String>> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || u32::from_be_bytes(magic) != EBML_HEADER {
+        return Ok(None);
+    }
+    let (header_size, _) = read_vint(&mut reader, false)?;
+    // The header only has a handful of short elements
+    if header_size > 1024 {
+        anyhow::bail!("EBML header is too large");
+    }
+    let mut header = vec![0; header_size as usize];
+    reader.read_exact(&mut header)?;
+    let doc_type = children(&header)
+        .into_iter()
+        .find(|&(id, _)| id == DOC_TYPE)
+        .map_or("matroska".into(), |(_, value)| read_string(value));
+    Ok(Some(doc_type))
+}
+
+// Reads the colour metadata of the given video track straight from the headers of a
+// Matroska file, which is much faster than running mkvinfo. Returns `None` if the file
+// isn't Matroska or the track has no colour metadata, so that mkvinfo can be used instead.
+pub fn parse_matroska(input: &Path, track: u64) -> Result<Option<Metadata>> {
+    parse_matroska_data(&mut BufReader::new(File::open(input)?), track)
+}
+
+fn parse_matroska_data<R: Read + Seek>(reader: &mut R, track: u64) -> Result<Option<Metadata>> {
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || u32::from_be_bytes(magic) != EBML_HEADER {
+        return Ok(None);
+    }
+    let segment = read_vint(reader, false)
+        .and_then(|(header_size, _)| skip(reader, header_size))
+        .and_then(|_| read_element_header(reader))
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => {
+                anyhow::anyhow!("Matroska file ends before its segment starts")
+            }
+            _ => e.into(),
+        })?;
+    if segment.0 != SEGMENT {
+        return Ok(None);
+    }
+
+    // The segment may be huge, so only its top level is walked, skipping everything
+    // but the info and the tracks. Muxers write both before the first cluster.
+    let mut title = None;
+    loop {
+        let (id, size) = match read_element_header(reader) {
+            Ok(header) => header,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if id == CLUSTER {
+            return Ok(None);
+        }
+        // Only clusters are commonly written with an unknown size, by live muxers.
+        // Finding where anything else ends means parsing all of it, so mkvinfo is left to that.
+        let size = match size {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        if id != TRACKS && id != INFO {
+            skip(reader, size)?;
+            continue;
+        }
+        if size > MAX_TRACKS_SIZE {
+            anyhow::bail!("Element {:#X} is too large", id);
+        }
+        let mut element = vec![0; size as usize];
+        if reader.read_exact(&mut element).is_err() {
+            anyhow::bail!("Matroska file ends partway through element {:#X}", id);
+        }
+        if id == INFO {
+            title = children(&element)
+                .into_iter()
+                .find(|&(id, _)| id == TITLE)
+                .map(|(_, value)| read_string(value));
+            continue;
+        }
+        return Ok(parse_tracks(&element, title, track));
+    }
+}
+
+// Reads the given video track, counting from 1
+fn parse_tracks(tracks: &[u8], title: Option<String>, track: u64) -> Option<Metadata> {
+    let mut video_tracks = 0;
+    for (id, entry) in children(tracks) {
+        if id != TRACK_ENTRY {
+            continue;
+        }
+        let fields = children(entry);
+        let is_video = fields
+            .iter()
+            .any(|&(id, value)| id == TRACK_TYPE && read_uint(value) == 1);
+        if !is_video {
+            continue;
+        }
+        video_tracks += 1;
+        if video_tracks != track {
+            continue;
+        }
+        let video: Vec<_> = fields
+            .iter()
+            .filter(|&&(id, _)| id == VIDEO)
+            .flat_map(|&(_, video)| children(video))
+            .collect();
+        let find = |wanted: u32| {
+            video
+                .iter()
+                .find(|&&(id, _)| id == wanted)
+                .map(|&(_, value)| value)
+        };
+        let mut metadata = find(COLOUR).and_then(parse_colour).unwrap_or_default();
+        metadata.title = title;
+        metadata.track_name = fields
+            .iter()
+            .find(|&&(id, _)| id == NAME)
+            .map(|&(_, value)| read_string(value));
+        // Progressive video usually only has the interlaced flag
+        metadata.field_order = find(FIELD_ORDER)
+            .map(|value| read_uint(value) as u8)
+            .or_else(|| {
+                find(FLAG_INTERLACED)
+                    .filter(|&value| read_uint(value) == 2)
+                    .map(|_| 0)
+            });
+        return Some(metadata).filter(|metadata| *metadata != Metadata::default());
+    }
+    None
+}
+
+fn parse_colour(colour: &[u8]) -> Option<Metadata> {
+    let mut basic = BasicMetadata::default();
+    let mut has_basic = false;
+    let mut hdr = HdrMetadata::default();
+    let mut has_hdr = false;
+    let mut chroma_siting = ChromaSiting::default();
+    let mut format = ColorFormat::default();
+    for (id, value) in children(colour) {
+        match id {
+            MATRIX_COEFFICIENTS => {
+                basic.matrix = Some(MatrixCoefficients::from_u8(read_uint(value) as u8));
+                has_basic = true;
+            }
+            RANGE => {
+                basic.range = ColorRange::from_mkvedit_prop(read_uint(value) as u8);
+                has_basic |= basic.range.is_some();
+            }
+            TRANSFER_CHARACTERISTICS => {
+                basic.transfer = Some(TransferCharacteristics::from_u8(read_uint(value) as u8));
+                has_basic = true;
+            }
+            PRIMARIES => {
+                basic.primaries = Some(ColorPrimaries::from_u8(read_uint(value) as u8));
+                has_basic = true;
+            }
+            CHROMA_SITING_HORZ => chroma_siting.horizontal = read_uint(value) as u8,
+            CHROMA_SITING_VERT => chroma_siting.vertical = read_uint(value) as u8,
+            BITS_PER_CHANNEL => format.bits_per_channel = read_uint(value) as u8,
+            CHROMA_SUBSAMPLING_HORZ => format.chroma_subsample_horizontal = read_uint(value) as u8,
+            CHROMA_SUBSAMPLING_VERT => format.chroma_subsample_vertical = read_uint(value) as u8,
+            CB_SUBSAMPLING_HORZ => format.cb_subsample_horizontal = read_uint(value) as u8,
+            CB_SUBSAMPLING_VERT => format.cb_subsample_vertical = read_uint(value) as u8,
+            MAX_CLL => hdr.max_content_light = read_uint(value) as u32,
+            MAX_FALL => hdr.max_frame_light = read_uint(value) as u32,
+            MASTERING_METADATA => {
+                parse_mastering_metadata(value, &mut hdr);
+                has_hdr = true;
+            }
+            _ => (),
+        }
+    }
+
+    if !has_basic && !has_hdr && !chroma_siting.is_specified() && !format.is_specified() {
+        return None;
+    }
+    Some(Metadata {
+        basic: if has_basic { Some(basic) } else { None },
+        hdr: if has_hdr { Some(hdr) } else { None },
+        chroma_siting: Some(chroma_siting).filter(ChromaSiting::is_specified),
+        color_format: Some(format).filter(ColorFormat::is_specified),
+        ..Default::default()
+    })
+}
+
+fn parse_mastering_metadata(mastering: &[u8], hdr: &mut HdrMetadata) {
+    let mut coords = ColorCoordinates::default();
+    let mut has_coords = false;
+    for (id, value) in children(mastering) {
+        let value = read_float(value);
+        match id {
+            PRIMARY_R_CHROMATICITY_X => coords.red.0 = value,
+            PRIMARY_R_CHROMATICITY_Y => coords.red.1 = value,
+            PRIMARY_G_CHROMATICITY_X => coords.green.0 = value,
+            PRIMARY_G_CHROMATICITY_Y => coords.green.1 = value,
+            PRIMARY_B_CHROMATICITY_X => coords.blue.0 = value,
+            PRIMARY_B_CHROMATICITY_Y => coords.blue.1 = value,
+            WHITE_POINT_CHROMATICITY_X => coords.white.0 = value,
+            WHITE_POINT_CHROMATICITY_Y => coords.white.1 = value,
+            LUMINANCE_MAX => hdr.max_luma = value,
+            LUMINANCE_MIN => hdr.min_luma = value,
+            _ => continue,
+        }
+        has_coords |= id != LUMINANCE_MAX && id != LUMINANCE_MIN;
+    }
+    if has_coords {
+        hdr.color_coords = Some(coords);
+    }
+}
+
+// Splits the body of a master element into its children's IDs and bodies.
+// A child with an unknown size takes up the rest of the body.
+// Anything after a malformed child is ignored.
+fn children(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut children = Vec::new();
+    while let Ok((id, size)) = read_element_header(&mut data) {
+        let size = size.unwrap_or(data.len() as u64);
+        if size > data.len() as u64 {
+            break;
+        }
+        let (body, rest) = data.split_at(size as usize);
+        children.push((id, body));
+        data = rest;
+    }
+    children
+}
+
+// Returns the element's ID and the size of its body, which is `None` if unknown
+fn read_element_header<R: Read>(reader: &mut R) -> io::Result<(u32, Option<u64>)> {
+    let (id, id_len) = read_vint(reader, true)?;
+    if id_len > 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid EBML element ID",
+        ));
+    }
+    let (size, size_len) = read_vint(reader, false)?;
+    // A size with every bit set means the size is unknown
+    let unknown = (1 << (7 * size_len)) - 1;
+    Ok((id as u32, if size == unknown { None } else { Some(size) }))
+}
+
+// EBML variable-length integers give their length in bytes by the number of leading
+// zeroes in the first byte, followed by a marker bit. Element IDs are conventionally
+// written with the marker bit included, while sizes are not.
+fn read_vint<R: Read>(reader: &mut R, keep_marker: bool) -> io::Result<(u64, u32)> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    let len = byte[0].leading_zeros() + 1;
+    if len > 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid EBML variable-length integer",
+        ));
+    }
+    let mut value = if keep_marker {
+        byte[0] as u64
+    } else {
+        byte[0] as u64 & (0xFF >> len)
+    };
+    for _ in 1..len {
+        reader.read_exact(&mut byte)?;
+        value = (value << 8) | byte[0] as u64;
+    }
+    Ok((value, len))
+}
+
+// Strings may be padded with zeroes
+fn read_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter()
+        .take(8)
+        .fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+fn read_float(data: &[u8]) -> f64 {
+    match data.len() {
+        4 => f32::from_be_bytes(data.try_into().expect("Length checked")) as f64,
+        8 => f64::from_be_bytes(data.try_into().expect("Length checked")),
+        _ => 0.,
+    }
+}
+
+fn skip<R: Seek>(reader: &mut R, size: u64) -> io::Result<()> {
+    let offset = i64::try_from(size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid EBML element size"))?;
+    reader.seek(SeekFrom::Current(offset))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::values::ColorRange;
+
+    // An element with a one or two byte size
+    fn element(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut element: Vec<u8> = id
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        match body.len() {
+            len @ 0..=0x7E => element.push(0x80 | len as u8),
+            len => element.extend([0x40 | (len >> 8) as u8, len as u8]),
+        }
+        element.extend(body);
+        element
+    }
+
+    // A segment of unknown size, as written by live muxers, with the given elements
+    // at its top level before a video track with BT.2020 colour metadata
+    fn matroska(before_tracks: &[u8], track_entry_size_known: bool) -> Vec<u8> {
+        let mut file = element(EBML_HEADER, &element(DOC_TYPE, b"matroska"));
+        file.extend(SEGMENT.to_be_bytes());
+        file.extend([0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        file.extend(before_tracks);
+        let mut entry = element(TRACK_TYPE, &[1]);
+        entry.extend(element(
+            VIDEO,
+            &element(COLOUR, &colour(&[(MATRIX_COEFFICIENTS, 9), (RANGE, 1)])),
+        ));
+        let entry = if track_entry_size_known {
+            element(TRACK_ENTRY, &entry)
+        } else {
+            [&[TRACK_ENTRY as u8, 0xFF][..], &entry].concat()
+        };
+        file.extend(element(TRACKS, &entry));
+        file.extend(element(CLUSTER, &[0; 16]));
+        file
+    }
+
+    fn parse(data: &[u8]) -> Result<Option<Metadata>> {
+        parse_matroska_data(&mut Cursor::new(data), 1)
+    }
+
+    fn check(metadata: Option<Metadata>) {
+        let basic = metadata.unwrap().basic.unwrap();
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+        assert_eq!(basic.range, Some(ColorRange::Limited));
+    }
+
+    #[test]
+    fn unknown_size_elements() {
+        let info = element(INFO, &element(TITLE, b"Movie"));
+        let metadata = parse(&matroska(&info, true)).unwrap().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Movie"));
+        check(Some(metadata));
+        // A child of unknown size ends with its parent
+        check(parse(&matroska(&info, false)).unwrap());
+        // Anything else of unknown size at the top level is left to mkvinfo
+        let tags = [&[0x12, 0x54, 0xC3, 0x67, 0xFF][..], &info].concat();
+        assert!(parse(&matroska(&tags, true)).unwrap().is_none());
+        let cluster = [&[0x1F, 0x43, 0xB6, 0x75, 0xFF][..], &[0; 16]].concat();
+        assert!(parse(&matroska(&cluster, true)).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_input() {
+        let file = matroska(&element(INFO, &element(TITLE, b"Movie")), true);
+        let tracks = file.len() - element(CLUSTER, &[0; 16]).len();
+        check(parse(&file[..tracks]).unwrap());
+        for len in 0..tracks {
+            match parse(&file[..len]) {
+                Ok(metadata) => assert!(metadata.is_none(), "{}", len),
+                Err(e) => assert!(e.to_string().contains("ends "), "{}: {}", len, e),
+            }
+        }
+        assert!(parse(&file[..(tracks - 1)]).is_err());
+    }
+
+    // Each element here has a two byte ID and a one byte value
+    fn colour(elements: &[(u32, u8)]) -> Vec<u8> {
+        elements
+            .iter()
+            .flat_map(|&(id, value)| [(id >> 8) as u8, id as u8, 0x81, value])
+            .collect()
+    }
+
+    #[test]
+    fn unset_range_is_not_read_as_full() {
+        let metadata = parse_colour(&colour(&[(MATRIX_COEFFICIENTS, 9), (RANGE, 0)])).unwrap();
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.range, None);
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+
+        let metadata = parse_colour(&colour(&[(MATRIX_COEFFICIENTS, 9), (RANGE, 3)])).unwrap();
+        assert_eq!(metadata.basic.unwrap().range, None);
+
+        // A range which isn't set is the same as no colour values at all
+        assert!(parse_colour(&colour(&[(RANGE, 0)])).is_none());
+    }
+
+    #[test]
+    fn set_range_is_read() {
+        let metadata = parse_colour(&colour(&[(RANGE, 1)])).unwrap();
+        assert_eq!(metadata.basic.unwrap().range, Some(ColorRange::Limited));
+        let metadata = parse_colour(&colour(&[(RANGE, 2)])).unwrap();
+        assert_eq!(metadata.basic.unwrap().range, Some(ColorRange::Full));
+    }
+}
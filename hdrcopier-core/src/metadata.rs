@@ -1,38 +1,152 @@
 use std::{
-    path::{Path, PathBuf},
+    ffi::OsStr,
+    fmt,
+    path::Path,
     process::Command,
+    thread::{self, JoinHandle},
 };
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tempfile::TempPath;
 
 use crate::{
-    parse::{parse_ffprobe, parse_mediainfo, parse_mkvinfo},
+    matroska::parse_matroska,
+    parse::{
+        is_mp4, parse_av1, parse_exr, parse_hevc, parse_master_display, parse_mkvinfo, parse_png,
+        Tool, ToolOutput,
+    },
+    tools::{log_command, tool_command, ToolCommand},
     values::{
-        color_range_to_mkvedit_prop, print_color_primaries, print_color_range,
-        print_matrix_coefficients, print_rav1e_color_primaries, print_rav1e_color_range,
-        print_rav1e_matrix_coefficients, print_rav1e_transfer_characteristics,
-        print_svtav1_color_primaries, print_svtav1_color_range, print_svtav1_matrix_coefficients,
-        print_svtav1_transfer_characteristics, print_transfer_characteristics,
-        print_x265_color_primaries, print_x265_color_range, print_x265_matrix_coefficients,
-        print_x265_transfer_characteristics,
+        ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics, UnknownValueError,
     },
+    MetadataOverrides,
 };
 
-#[derive(Default)]
+// Every property within the Matroska Colour element, as named by mkvpropedit
+const COLOUR_PROPERTIES: &[&str] = &[
+    "colour-matrix-coefficients",
+    "colour-bits-per-channel",
+    "chroma-subsample-horizontal",
+    "chroma-subsample-vertical",
+    "cb-subsample-horizontal",
+    "cb-subsample-vertical",
+    "chroma-siting-horizontal",
+    "chroma-siting-vertical",
+    "colour-range",
+    "colour-transfer-characteristics",
+    "colour-primaries",
+    "max-content-light",
+    "max-frame-light",
+    "chromaticity-coordinates-red-x",
+    "chromaticity-coordinates-red-y",
+    "chromaticity-coordinates-green-x",
+    "chromaticity-coordinates-green-y",
+    "chromaticity-coordinates-blue-x",
+    "chromaticity-coordinates-blue-y",
+    "white-coordinates-x",
+    "white-coordinates-y",
+    "max-luminance",
+    "min-luminance",
+];
+
+// The encoder and muxer formats printed by `show --format all`, in order
+const ALL_FORMATS: &[&str] = &["x265", "x264", "svt-av1", "rav1e", "aomenc", "mkvmerge"];
+
+// Floats which differ by less than these are considered equal when comparing files.
+// Coordinates are stored in units of 0.00002 and minimum luminance in units of 0.0001,
+// so anything smaller than half a unit is only float noise, e.g. from mkvinfo.
+const COORDINATE_EPSILON: f64 = 0.00001;
+const LUMINANCE_EPSILON: f64 = 0.00005;
+
+#[derive(Default, PartialEq, Serialize, Deserialize)]
+// Fields added since the JSON was saved are left empty
+#[serde(default)]
 pub struct Metadata {
     pub basic: Option<BasicMetadata>,
     pub hdr: Option<HdrMetadata>,
+    // Dolby Vision is only copied with `--dolby-vision`, since it means rewriting the video stream
+    pub dolby_vision: Option<DoviMetadata>,
+    // Likewise, HDR10+ is only copied with `--hdr10plus`
+    pub hdr10plus: bool,
+    // These are only shown, never copied, since changing them means re-encoding
+    pub bit_depth: Option<u8>,
+    // e.g. `4:2:0`
+    pub chroma_subsampling: Option<String>,
+    pub chroma_siting: Option<ChromaSiting>,
+    // Unlike the bit depth and subsampling above, this is read from the container
+    // headers, and is only copied when the input's headers have it
+    pub color_format: Option<ColorFormat>,
+    // Using the values from Matroska, e.g. 0 for progressive and 1 for top field first.
+    // This is only copied with `--field-order`.
+    pub field_order: Option<u8>,
+    // The segment's title and the video track's name, which are only read from
+    // the container headers, and only copied with `--copy-title`
+    pub title: Option<String>,
+    pub track_name: Option<String>,
+}
+
+// Where the chroma samples are relative to the luma samples, using the values from
+// Matroska: 0 is unspecified, 1 is collocated with the left or top luma sample,
+// and 2 is halfway between them
+#[derive(Default, PartialEq, Serialize, Deserialize)]
+pub struct ChromaSiting {
+    pub horizontal: u8,
+    pub vertical: u8,
+}
+
+impl ChromaSiting {
+    // The parsers only keep siting which says something
+    pub fn is_specified(&self) -> bool {
+        self.horizontal != 0 || self.vertical != 0
+    }
+}
+
+// The rest of the Matroska Colour element, which describes the video stream's format.
+// The subsampling is how many pixels are removed between each chroma sample, e.g.
+// 1 both ways for 4:2:0, and the Cb subsampling is added to it for the Cb channel.
+// Zero means unspecified for the bits per channel.
+#[derive(Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorFormat {
+    pub bits_per_channel: u8,
+    pub chroma_subsample_horizontal: u8,
+    pub chroma_subsample_vertical: u8,
+    pub cb_subsample_horizontal: u8,
+    pub cb_subsample_vertical: u8,
+}
+
+impl ColorFormat {
+    // Like the siting, zeroes aren't worth writing
+    pub fn is_specified(&self) -> bool {
+        self.bits_per_channel != 0
+            || self.chroma_subsample_horizontal != 0
+            || self.chroma_subsample_vertical != 0
+            || self.cb_subsample_horizontal != 0
+            || self.cb_subsample_vertical != 0
+    }
+}
+
+#[derive(Default, PartialEq, Serialize, Deserialize)]
+pub struct DoviMetadata {
+    // Not every tool reports the profile and level
+    pub profile: Option<u8>,
+    pub level: Option<u8>,
+    // Whether the video stream carries an RPU, which holds the dynamic metadata itself
+    pub rpu_present: bool,
 }
 
-#[derive(Default)]
+// Each value is `None` if the input doesn't specify it, so that it's left as it is
+// on the target rather than being overwritten with a guess
+#[derive(Default, PartialEq, Serialize, Deserialize)]
 pub struct BasicMetadata {
-    pub matrix: u8,
-    pub range: u8,
-    pub transfer: u8,
-    pub primaries: u8,
+    pub matrix: Option<MatrixCoefficients>,
+    pub range: Option<ColorRange>,
+    pub transfer: Option<TransferCharacteristics>,
+    pub primaries: Option<ColorPrimaries>,
 }
 
-#[derive(Default)]
+#[derive(Default, PartialEq, Serialize, Deserialize)]
 pub struct ColorCoordinates {
     pub red: (f64, f64),
     pub green: (f64, f64),
@@ -40,16 +154,341 @@ pub struct ColorCoordinates {
     pub white: (f64, f64),
 }
 
-#[derive(Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhitePoint {
+    D65,
+    D60,
+    Dci,
+    Other,
+}
+
+impl WhitePoint {
+    pub fn name(self) -> &'static str {
+        match self {
+            WhitePoint::D65 => "D65",
+            WhitePoint::D60 => "D60",
+            WhitePoint::Dci => "DCI",
+            WhitePoint::Other => "Unknown",
+        }
+    }
+}
+
+// The overall HDR format, which is what most people know a file by,
+// rather than its individual values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    Sdr,
+    // PQ without any mastering display or content light levels
+    Pq,
+    Hdr10,
+    Hdr10Plus,
+    Hlg,
+    DolbyVision,
+}
+
+impl HdrFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            HdrFormat::Sdr => "SDR",
+            HdrFormat::Pq => "PQ",
+            HdrFormat::Hdr10 => "HDR10",
+            HdrFormat::Hdr10Plus => "HDR10+",
+            HdrFormat::Hlg => "HLG",
+            HdrFormat::DolbyVision => "Dolby Vision",
+        }
+    }
+}
+
+impl ColorCoordinates {
+    // The tolerance is loose enough to treat WP(15634,16450),
+    // which is very commonly used, as D65.
+    pub fn white_point(&self) -> WhitePoint {
+        const TOLERANCE: f64 = 0.0005;
+        let matches = |(x, y): (f64, f64)| {
+            (self.white.0 - x).abs() < TOLERANCE && (self.white.1 - y).abs() < TOLERANCE
+        };
+        if matches((0.3127, 0.3290)) {
+            WhitePoint::D65
+        } else if matches((0.32168, 0.33767)) {
+            WhitePoint::D60
+        } else if matches((0.314, 0.351)) {
+            WhitePoint::Dci
+        } else {
+            WhitePoint::Other
+        }
+    }
+
+    // Finds the standard primaries these coordinates belong to, for formats such as
+    // images which give the coordinates rather than a code. The white point is only
+    // needed to tell the two P3 variants apart.
+    pub fn primaries(&self) -> ColorPrimaries {
+        const TOLERANCE: f64 = 0.0005;
+        let matches = |a: (f64, f64), b: (f64, f64)| {
+            (a.0 - b.0).abs() < TOLERANCE && (a.1 - b.1).abs() < TOLERANCE
+        };
+        let known = [
+            (
+                ColorPrimaries::Bt709,
+                (0.64, 0.33),
+                (0.30, 0.60),
+                (0.15, 0.06),
+            ),
+            (
+                ColorPrimaries::Bt470Bg,
+                (0.64, 0.33),
+                (0.29, 0.60),
+                (0.15, 0.06),
+            ),
+            (
+                ColorPrimaries::Smpte170M,
+                (0.63, 0.34),
+                (0.31, 0.595),
+                (0.155, 0.07),
+            ),
+            (
+                ColorPrimaries::Bt2020,
+                (0.708, 0.292),
+                (0.17, 0.797),
+                (0.131, 0.046),
+            ),
+            (
+                ColorPrimaries::Smpte432,
+                (0.68, 0.32),
+                (0.265, 0.69),
+                (0.15, 0.06),
+            ),
+        ];
+        let primaries = known
+            .into_iter()
+            .find(|&(_, red, green, blue)| {
+                matches(self.red, red) && matches(self.green, green) && matches(self.blue, blue)
+            })
+            .map_or(ColorPrimaries::Unspecified, |(primaries, ..)| primaries);
+        if primaries == ColorPrimaries::Smpte432 && self.white_point() == WhitePoint::Dci {
+            return ColorPrimaries::Smpte431;
+        }
+        primaries
+    }
+}
+
+#[derive(Default, PartialEq, Serialize, Deserialize)]
 pub struct HdrMetadata {
     pub color_coords: Option<ColorCoordinates>,
-    pub max_luma: u32,
+    pub max_luma: f64,
     pub min_luma: f64,
     pub max_content_light: u32,
     pub max_frame_light: u32,
 }
 
+impl HdrMetadata {
+    // Some files only have content light levels, in which case the
+    // luminances are meaningless zeros which shouldn't be written
+    pub(crate) fn has_mastering_display(&self) -> bool {
+        self.color_coords.is_some() || self.max_luma > 0.0
+    }
+
+    pub(crate) fn same_mastering_display(&self, other: &HdrMetadata) -> bool {
+        self.color_coords == other.color_coords
+            && self.max_luma == other.max_luma
+            && self.min_luma == other.min_luma
+    }
+
+    pub(crate) fn describe_mastering_display(&self) -> String {
+        match self.color_coords {
+            Some(ref coords) => format_master_display(coords, self.max_luma, self.min_luma),
+            None => format!("L({},{})", self.max_luma, self.min_luma),
+        }
+    }
+}
+
+// The human-readable format shown by `hdrcopier show`
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "HDR Format: {}", self.hdr_format().name())?;
+        if let Some(bit_depth) = self.bit_depth {
+            writeln!(f, "Bit Depth: {}", bit_depth)?;
+        }
+        if let Some(ref chroma_subsampling) = self.chroma_subsampling {
+            writeln!(f, "Chroma Subsampling: {}", chroma_subsampling)?;
+        }
+        if let Some(ref chroma_siting) = self.chroma_siting {
+            writeln!(
+                f,
+                "Horizontal Chroma Siting: {}",
+                siting_name(chroma_siting.horizontal, "Left")
+            )?;
+            writeln!(
+                f,
+                "Vertical Chroma Siting: {}",
+                siting_name(chroma_siting.vertical, "Top")
+            )?;
+        }
+        if let Some(field_order) = self.field_order {
+            writeln!(f, "Field Order: {}", field_order_name(field_order))?;
+        }
+        if let Some(ref title) = self.title {
+            writeln!(f, "Title: {}", title)?;
+        }
+        if let Some(ref track_name) = self.track_name {
+            writeln!(f, "Track Name: {}", track_name)?;
+        }
+        if let Some(ref basic) = self.basic {
+            write!(f, "{}", basic)?;
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            write!(f, "{}", hdr_data)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BasicMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(range) = self.range {
+            writeln!(f, "Color Range: {}", range)?;
+        }
+        if let Some(primaries) = self.primaries {
+            writeln!(f, "Color Primaries: {}", primaries)?;
+        }
+        if let Some(transfer) = self.transfer {
+            writeln!(f, "Transfer Characteristics: {}", transfer)?;
+        }
+        if let Some(matrix) = self.matrix {
+            writeln!(f, "Matrix Coefficients: {}", matrix)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for HdrMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Max Content Light Level: {}", self.max_content_light)?;
+        writeln!(f, "Max Frame-Average Light Level: {}", self.max_frame_light)?;
+        writeln!(f, "Maximum Luminance: {}", self.max_luma)?;
+        writeln!(f, "Minimum Luminance: {}", self.min_luma)?;
+        if let Some(ref color_coords) = self.color_coords {
+            write!(f, "{}", color_coords)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ColorCoordinates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Red Coordinates: {:.5}, {:.5}", self.red.0, self.red.1)?;
+        writeln!(
+            f,
+            "Green Coordinates: {:.5}, {:.5}",
+            self.green.0, self.green.1
+        )?;
+        writeln!(
+            f,
+            "Blue Coordinates: {:.5}, {:.5}",
+            self.blue.0, self.blue.1
+        )?;
+        writeln!(
+            f,
+            "White Point Coordinates: {:.5}, {:.5}",
+            self.white.0, self.white.1
+        )?;
+        writeln!(f, "White Point: {}", self.white_point().name())
+    }
+}
+
+fn field_order_name(value: u8) -> String {
+    match value {
+        0 => "Progressive".to_string(),
+        1 => "Top Field First".to_string(),
+        2 => "Undetermined".to_string(),
+        6 => "Bottom Field First".to_string(),
+        9 => "Bottom Field First (Swapped)".to_string(),
+        14 => "Top Field First (Swapped)".to_string(),
+        _ => format!("Unknown ({})", value),
+    }
+}
+
+fn siting_name(value: u8, collocated: &str) -> String {
+    match value {
+        0 => "Unspecified".to_string(),
+        1 => collocated.to_string(),
+        2 => "Half".to_string(),
+        _ => format!("Unknown ({})", value),
+    }
+}
+
 impl Metadata {
+    // Combines the metadata found by two parsers of the same file, preferring this one's:
+    // - Anything which only one of them found is kept, whichever it's from
+    // - The colour values are taken as a whole from `other` only if this has none
+    // - The HDR metadata is taken as a whole from `other` if this has none, or if only
+    //   `other` has the mastering display coordinates, since some tools only find the
+    //   content light levels
+    // - Dynamic HDR metadata counts as present if either of them found it
+    pub fn merge(self, other: Metadata) -> Metadata {
+        let hdr = if self.takes_hdr_from(&other) {
+            other.hdr
+        } else {
+            self.hdr
+        };
+        Metadata {
+            basic: self.basic.or(other.basic),
+            hdr,
+            dolby_vision: self.dolby_vision.or(other.dolby_vision),
+            hdr10plus: self.hdr10plus || other.hdr10plus,
+            bit_depth: self.bit_depth.or(other.bit_depth),
+            chroma_subsampling: self.chroma_subsampling.or(other.chroma_subsampling),
+            chroma_siting: self.chroma_siting.or(other.chroma_siting),
+            color_format: self.color_format.or(other.color_format),
+            field_order: self.field_order.or(other.field_order),
+            title: self.title.or(other.title),
+            track_name: self.track_name.or(other.track_name),
+        }
+    }
+
+    fn takes_hdr_from(&self, other: &Metadata) -> bool {
+        match (&self.hdr, &other.hdr) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(ours), Some(theirs)) => {
+                ours.color_coords.is_none() && theirs.color_coords.is_some()
+            }
+        }
+    }
+
+    // Whether there's nothing left for another tool to find, apart from dynamic metadata
+    fn is_complete(&self) -> bool {
+        self.basic.is_some()
+            && self
+                .hdr
+                .as_ref()
+                .is_some_and(|hdr| hdr.color_coords.is_some())
+    }
+
+    // Whether none of the tools found any colour or HDR metadata at all.
+    // The bit depth and chroma layout don't count, since every video has them.
+    pub fn is_empty(&self) -> bool {
+        self.basic.is_none() && self.hdr.is_none() && self.dolby_vision.is_none() && !self.hdr10plus
+    }
+
+    // Dynamic metadata takes precedence, since players which support it use it
+    // instead of the static metadata. Files with only static HDR metadata and no
+    // transfer are assumed to be HDR10, since that metadata is meaningless otherwise.
+    pub fn hdr_format(&self) -> HdrFormat {
+        if self.dolby_vision.is_some() {
+            return HdrFormat::DolbyVision;
+        }
+        if self.hdr10plus {
+            return HdrFormat::Hdr10Plus;
+        }
+        match self.basic.as_ref().and_then(|basic| basic.transfer) {
+            Some(TransferCharacteristics::Hlg) => HdrFormat::Hlg,
+            Some(TransferCharacteristics::Pq) if self.hdr.is_none() => HdrFormat::Pq,
+            Some(TransferCharacteristics::Pq) => HdrFormat::Hdr10,
+            None if self.hdr.is_some() => HdrFormat::Hdr10,
+            _ => HdrFormat::Sdr,
+        }
+    }
+
     // Why do we have to go through all three of these?
     //
     // WELL, I'm glad you asked.
@@ -58,353 +497,1968 @@ impl Metadata {
     // Just to be sure we didn't miss it.
     //
     // Encoding is dumb.
-    pub fn parse(input: &Path) -> Result<Self> {
+    //
+    // If `from_bitstream` is set, we skip mkvinfo, since it only reads
+    // the container headers, and rely on what's in the video stream itself.
+    //
+    // `track` is which video track to read, counting from 1.
+    pub fn parse(input: &Path, from_bitstream: bool, track: u64) -> Result<Self> {
+        // The tools are independent of each other, so we run them all at once
+        // and then merge their results in order of preference.
+        // Reading the headers ourselves is much faster than running mkvinfo,
+        // which is only needed if that fails or finds nothing
+        let native = parse_native(input, from_bitstream, track).unwrap_or_else(|e| {
+            log::warn!("Failed to read headers of {:?}: {}", input, e);
+            None
+        });
+        // MP4 has no Matroska headers, but the `colr` box is its equivalent of the Colour element
+        let mp4 = !from_bitstream && native.is_none() && is_mp4(input).unwrap_or(false);
+        let mkvinfo = if from_bitstream || native.is_some() || mp4 {
+            None
+        } else {
+            Some(spawn_parser(input, Tool::Mkvinfo, track))
+        };
+        let mediainfo = spawn_parser(input, Tool::Mediainfo, track);
+        let ffprobe = spawn_parser(input, Tool::Ffprobe, track);
+        let container_reader = if native.is_some() {
+            "hdrcopier's own parser"
+        } else {
+            "mkvinfo"
+        };
+        let mkvinfo = native.map(Ok).or_else(|| mkvinfo.map(join_parser));
+        let mediainfo = join_parser(mediainfo);
+        let mut ffprobe = join_parser(ffprobe);
+
         let mut data = Metadata::default();
-        match parse_mkvinfo(input) {
-            Ok(info) => {
-                data = info;
-            }
-            Err(e) => {
-                eprintln!("Warning: {}", e);
+        if let Some(mkvinfo) = mkvinfo {
+            match mkvinfo {
+                Ok(info) => {
+                    log::debug!("Read headers with {}", container_reader);
+                    data = info;
+                }
+                Err(e) => {
+                    log::warn!("{}", e);
+                }
             }
         }
-        if data.basic.is_some()
-            && data.hdr.is_some()
-            && data.hdr.as_ref().unwrap().color_coords.is_some()
-        {
-            return Ok(data);
+        // ffprobe reports the `colr` box's code points as the stream's colour values,
+        // so for MP4 they take the place of the container headers. The rest of ffprobe's
+        // output is only used if mediainfo doesn't find it, as for other containers.
+        if mp4 {
+            if let Some(basic) = ffprobe.as_mut().ok().and_then(|info| info.basic.take()) {
+                log::debug!("Read the colr box with ffprobe");
+                data.basic = Some(basic);
+            }
         }
+        let complete = data.is_complete();
 
-        match parse_mediainfo(input) {
-            Ok(info) => {
-                if data.basic.is_none() && info.basic.is_some() {
-                    data.basic = info.basic;
-                }
-                if info.hdr.is_some() {
-                    data.hdr = info.hdr;
-                }
+        // Even if mkvinfo found everything, we still run mediainfo,
+        // because dynamic HDR metadata is only in the video stream
+        // and we need to warn the user that we won't copy it.
+        match mediainfo {
+            Ok(info) => data = merge_tool(data, info, Tool::Mediainfo),
+            Err(e) if complete => {
+                log::warn!("{}", e);
+                return Ok(data);
             }
             Err(e) => {
-                eprintln!("Error: {}", e);
+                log::error!("{}", e);
                 anyhow::bail!("Unable to parse metadata");
             }
         }
-        if data.hdr.is_some() && data.hdr.as_ref().unwrap().color_coords.is_some() {
+        if data.is_complete() {
             return Ok(data);
         }
 
-        match parse_ffprobe(input) {
-            Ok(Some(info)) => {
-                data.hdr = Some(info);
-            }
-            Ok(None) => (),
+        match ffprobe {
+            Ok(info) => data = merge_tool(data, info, Tool::Ffprobe),
             Err(e) => {
-                eprintln!("Warning: {}", e);
+                log::warn!("{}", e);
             }
         }
 
         Ok(data)
     }
 
-    pub fn apply(&self, target: &Path, chapters: Option<&Path>) -> Result<()> {
-        let mut command = self.build_mkvmerge_command(target, chapters);
-        eprintln!("Running: {:?}", command);
-        let status = command.status()?;
-        if !status.success() {
-            anyhow::bail!("Failed to mux metadata");
+    // If `strip_existing` is set, all colour properties on the target are
+    // removed before our values are written, so that the target ends up with
+    // exactly the elements we set and nothing left over from other tools.
+    //
+    // `extra_args` are passed to mkvpropedit verbatim, after all of our own arguments.
+    //
+    // `track` is the ordinal of the video track to edit, starting from 1.
+    //
+    // Returns the command which was run, formatted for a shell.
+    pub fn apply(
+        &self,
+        target: &Path,
+        track: u64,
+        chapters: Option<&Path>,
+        strip_existing: bool,
+        extra_args: &[String],
+        dry_run: bool,
+    ) -> Result<String> {
+        if self.basic.is_none() && self.hdr.is_none() {
+            log::warn!("No colour metadata was found, so there is nothing to copy");
         }
-        Ok(())
+        // mkvpropedit succeeds even when every property already had its value,
+        // so the target's properties are compared before and after instead.
+        // Chapters and extra arguments change things which aren't compared.
+        let before = if dry_run || chapters.is_some() || !extra_args.is_empty() {
+            None
+        } else {
+            Metadata::parse_container(target, track).ok()
+        };
+        let command =
+            self.build_mkvmerge_command(target, track, chapters, strip_existing, extra_args);
+        let formatted = run_mkvpropedit(command, target, dry_run)?;
+        if let Some(before) = before {
+            let after = Metadata::parse_container(target, track)?;
+            if before.container_properties() == after.container_properties() {
+                log::warn!(
+                    "mkvpropedit made no changes to the metadata on {}; it may already have had it",
+                    target.display()
+                );
+            }
+        }
+        Ok(formatted)
+    }
+
+    // Parses only the container headers, which are what mkvpropedit edits
+    pub fn parse_container(input: &Path, track: u64) -> Result<Self> {
+        if let Some(metadata) = parse_matroska(input, track)? {
+            return Ok(metadata);
+        }
+        parse_mkvinfo(
+            &ToolOutput::global().get(Tool::Mkvinfo, input, track)?,
+            track,
+        )
+    }
+
+    // Deletes each of the properties present in this metadata, which should have been
+    // parsed from the target's container headers. Returns false if there were none.
+    pub fn strip(&self, target: &Path, track: u64, dry_run: bool) -> Result<bool> {
+        let properties = self.mkvpropedit_properties();
+        if properties.is_empty() {
+            return Ok(false);
+        }
+
+        let mut command = tool_command("mkvpropedit");
+        command.arg("-e").arg(format!("track:v{}", track));
+        for (property, _) in properties {
+            command.arg("-d").arg(property);
+        }
+        command.arg(target);
+        run_mkvpropedit(command, target, dry_run)?;
+        Ok(true)
     }
 
-    pub fn print(&self, format: Option<&str>) {
+    pub fn print(&self, format: Option<&str>) -> Result<()> {
         match format {
             None => self.print_human_readable_format(),
-            Some("x265") => self.print_x265_args(),
-            Some("svt-av1") => self.print_svtav1_args(),
-            Some("rav1e") => self.print_rav1e_args(),
+            Some("x265") => self.print_x265_args()?,
+            Some("x264") => self.print_x264_args()?,
+            Some("svt-av1") => self.print_svtav1_args()?,
+            Some("rav1e") => self.print_rav1e_args()?,
+            Some("aomenc") => self.print_aomenc_args()?,
             Some("mkvmerge") => self.print_mkvmerge_args(),
+            Some("tonemap") => self.print_tonemap_filter(),
+            Some("mkvtoolnix-gui") => self.print_mkvtoolnix_gui_format(),
+            Some("st2086") => self.print_st2086_json()?,
+            Some("env") => self.print_env_vars(),
+            Some("numeric") => self.print_numeric_values(),
+            Some("vapoursynth") => self.print_vapoursynth_args()?,
+            Some("json") => self.print_json()?,
+            Some("master-display") => self.print_master_display()?,
+            Some("all") => self.print_all_formats(),
             _ => unreachable!("Unimplemented output format"),
         }
+        Ok(())
+    }
+
+    // Prints only the mastering display and content light levels, in x265's format,
+    // for pasting into an encoder config
+    fn print_master_display(&self) -> Result<()> {
+        let (hdr_data, color_coords) = match self.hdr {
+            Some(ref hdr_data) => match hdr_data.color_coords {
+                Some(ref color_coords) => (hdr_data, color_coords),
+                None => anyhow::bail!("File has no mastering display metadata"),
+            },
+            None => anyhow::bail!("File has no HDR metadata"),
+        };
+        println!(
+            "{}",
+            format_master_display(color_coords, hdr_data.max_luma, hdr_data.min_luma)
+        );
+        println!(
+            "{},{}",
+            hdr_data.max_content_light, hdr_data.max_frame_light
+        );
+        Ok(())
+    }
+
+    fn print_all_formats(&self) {
+        for (i, format) in ALL_FORMATS.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", format);
+            // One encoder not supporting a value shouldn't hide the others
+            if let Err(e) = self.print(Some(format)) {
+                log::warn!("{}", e);
+            }
+        }
     }
 
     fn print_human_readable_format(&self) {
+        print!("{}", self);
+    }
+
+    fn print_x265_args(&self) -> Result<()> {
+        println!("{}", self.x265_args()?);
+        Ok(())
+    }
+
+    fn x265_args(&self) -> Result<String> {
+        let mut args = Vec::new();
         if let Some(ref basic) = self.basic {
-            println!("Color Range: {}", print_color_range(basic.range));
-            println!(
-                "Color Primaries: {}",
-                print_color_primaries(basic.primaries)
-            );
-            println!(
-                "Transfer Characteristics: {}",
-                print_transfer_characteristics(basic.transfer)
-            );
-            println!(
-                "Matrix Coefficients: {}",
-                print_matrix_coefficients(basic.matrix)
-            );
+            if let Some(range) = basic.range {
+                args.push(format!(
+                    "--range {}",
+                    x265_value_or_unknown(range.x265_name())?
+                ));
+            }
+            if let Some(primaries) = basic.primaries {
+                args.push(format!(
+                    "--colorprim {}",
+                    x265_value_or_unknown(primaries.x265_name())?
+                ));
+            }
+            if let Some(transfer) = basic.transfer {
+                args.push(format!(
+                    "--transfer {}",
+                    x265_value_or_unknown(transfer.x265_name())?
+                ));
+            }
+            if let Some(matrix) = basic.matrix {
+                args.push(format!(
+                    "--colormatrix {}",
+                    x265_value_or_unknown(matrix.x265_name())?
+                ));
+            }
         }
         if let Some(ref hdr_data) = self.hdr {
-            println!("Max Content Light Level: {}", hdr_data.max_content_light);
-            println!(
-                "Max Frame-Average Light Level: {}",
-                hdr_data.max_frame_light
-            );
-            println!("Maximum Luminance: {}", hdr_data.max_luma);
-            println!("Minimum Luminance: {}", hdr_data.min_luma);
+            if hdr_data.max_luma > 0.0 {
+                args.push(format!("--max-luma {}", hdr_data.max_luma.round() as u32));
+            }
+            if hdr_data.min_luma > 0.0 {
+                args.push(format!("--min-luma {}", hdr_data.min_luma.round() as u32));
+            }
+            args.push(format!(
+                "--max-cll {},{}",
+                hdr_data.max_content_light, hdr_data.max_frame_light
+            ));
             if let Some(ref color_coords) = hdr_data.color_coords {
-                println!(
-                    "Red Coordinates: {:.5}, {:.5}",
-                    color_coords.red.0, color_coords.red.1
-                );
-                println!(
-                    "Green Coordinates: {:.5}, {:.5}",
-                    color_coords.green.0, color_coords.green.1
-                );
-                println!(
-                    "Blue Coordinates: {:.5}, {:.5}",
-                    color_coords.blue.0, color_coords.blue.1
-                );
-                println!(
-                    "White Point Coordinates: {:.5}, {:.5}",
-                    color_coords.white.0, color_coords.white.1
-                );
+                args.push(format!(
+                    "--master-display {}",
+                    shell_quote(&format_master_display(
+                        color_coords,
+                        hdr_data.max_luma,
+                        hdr_data.min_luma
+                    ))
+                ));
             }
         }
+        Ok(args.join(" "))
     }
 
-    fn print_x265_args(&self) {
-        println!(
-            "{}{}",
-            if let Some(ref basic) = self.basic {
-                format!(
-                    "--range {} --colorprim {} --transfer {} --colormatrix {}",
-                    print_x265_color_range(basic.range),
-                    print_x265_color_primaries(basic.primaries),
-                    print_x265_transfer_characteristics(basic.transfer),
-                    print_x265_matrix_coefficients(basic.matrix)
-                )
-            } else {
-                String::new()
-            },
-            if let Some(ref hdr_data) = self.hdr {
-                format!(
-                    " {}{} --max-cll {},{} --master-display {}",
-                    if hdr_data.max_luma > 0 {
-                        format!("--max-luma {} ", hdr_data.max_luma)
-                    } else {
-                        String::new()
-                    },
-                    if hdr_data.min_luma > 0.0 {
-                        format!("--min-luma {} ", hdr_data.min_luma.round() as u32)
-                    } else {
-                        String::new()
-                    },
-                    hdr_data.max_content_light,
-                    hdr_data.max_frame_light,
-                    format_master_display(
-                        hdr_data.color_coords.as_ref().unwrap(),
+    fn print_x264_args(&self) -> Result<()> {
+        let mut args = Vec::new();
+        if let Some(ref basic) = self.basic {
+            if let Some(range) = basic.range {
+                args.push(format!("--range {}", range.x264_name()?));
+            }
+            if let Some(primaries) = basic.primaries {
+                args.push(format!("--colorprim {}", primaries.x264_name()?));
+            }
+            if let Some(transfer) = basic.transfer {
+                args.push(format!("--transfer {}", transfer.x264_name()?));
+            }
+            if let Some(matrix) = basic.matrix {
+                args.push(format!("--colormatrix {}", matrix.x264_name()?));
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                args.push(format!(
+                    "--mastering-display {}",
+                    shell_quote(&format_master_display(
+                        color_coords,
                         hdr_data.max_luma,
                         hdr_data.min_luma
-                    )
-                )
-            } else {
-                String::new()
+                    ))
+                ));
             }
-        );
+            args.push(format!(
+                "--cll {},{}",
+                hdr_data.max_content_light, hdr_data.max_frame_light
+            ));
+        }
+        println!("{}", args.join(" "));
+        Ok(())
     }
 
-    fn print_svtav1_args(&self) {
-        println!(
-            "{}{}",
-            if let Some(ref basic) = self.basic {
-                format!(
-                    "--color-range {} --color-primaries {} --transfer-characteristics {} --matrix-coefficients {}",
-                    print_svtav1_color_range(basic.range),
-                    print_svtav1_color_primaries(basic.primaries),
-                    print_svtav1_transfer_characteristics(basic.transfer),
-                    print_svtav1_matrix_coefficients(basic.matrix)
-                )
-            } else {
-                String::new()
-            },
-            if let Some(ref hdr_data) = self.hdr {
-                format!(
-                    " --content-light {},{} --mastering-display {}",
-                    hdr_data.max_content_light,
-                    hdr_data.max_frame_light,
-                    format!(
-                        "G({},{})B({},{})R({},{})WP({},{})L({},{})",
-                        hdr_data.color_coords.as_ref().unwrap().green.0,
-                        hdr_data.color_coords.as_ref().unwrap().green.1,
-                        hdr_data.color_coords.as_ref().unwrap().blue.0,
-                        hdr_data.color_coords.as_ref().unwrap().blue.1,
-                        hdr_data.color_coords.as_ref().unwrap().red.0,
-                        hdr_data.color_coords.as_ref().unwrap().red.1,
-                        hdr_data.color_coords.as_ref().unwrap().white.0,
-                        hdr_data.color_coords.as_ref().unwrap().white.1,
-                        hdr_data.max_luma,
-                        hdr_data.min_luma,
-                    ),
-                )
-            } else {
-                String::new()
+    // SvtAv1EncApp takes the numeric codes for the color enums directly
+    fn print_svtav1_args(&self) -> Result<()> {
+        let mut args = Vec::new();
+        if self.hdr.is_some() {
+            args.push("--enable-hdr 1".to_string());
+        }
+        if let Some(ref basic) = self.basic {
+            if let Some(primaries) = basic.primaries {
+                args.push(format!("--color-primaries {}", primaries.as_u8()));
             }
-        );
-    }
-
-    fn print_rav1e_args(&self) {
-        println!(
-            "{}{}",
-            if let Some(ref basic) = self.basic {
-                format!(
-                    "--range {} --primaries {} --transfer {} --matrix {}",
-                    print_rav1e_color_range(basic.range),
-                    print_rav1e_color_primaries(basic.primaries),
-                    print_rav1e_transfer_characteristics(basic.transfer),
-                    print_rav1e_matrix_coefficients(basic.matrix)
-                )
-            } else {
-                String::new()
-            },
-            if let Some(ref hdr_data) = self.hdr {
-                format!(
-                    " --content-light {},{} --mastering-display {}",
-                    hdr_data.max_content_light,
-                    hdr_data.max_frame_light,
-                    format_master_display(
-                        hdr_data.color_coords.as_ref().unwrap(),
-                        hdr_data.max_luma,
-                        hdr_data.min_luma
-                    )
-                )
-            } else {
-                String::new()
+            if let Some(transfer) = basic.transfer {
+                args.push(format!("--transfer-characteristics {}", transfer.as_u8()));
             }
-        );
+            if let Some(matrix) = basic.matrix {
+                args.push(format!("--matrix-coefficients {}", matrix.as_u8()));
+            }
+            if let Some(range) = basic.range {
+                args.push(format!("--color-range {}", range.to_av1()?));
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                let mastering_display = format!(
+                    "G({:.5},{:.5})B({:.5},{:.5})R({:.5},{:.5})WP({:.5},{:.5})L({},{})",
+                    color_coords.green.0,
+                    color_coords.green.1,
+                    color_coords.blue.0,
+                    color_coords.blue.1,
+                    color_coords.red.0,
+                    color_coords.red.1,
+                    color_coords.white.0,
+                    color_coords.white.1,
+                    hdr_data.max_luma,
+                    hdr_data.min_luma,
+                );
+                args.push(format!(
+                    "--mastering-display {}",
+                    shell_quote(&mastering_display)
+                ));
+            }
+            args.push(format!(
+                "--content-light {},{}",
+                hdr_data.max_content_light, hdr_data.max_frame_light
+            ));
+        }
+        println!("{}", args.join(" "));
+        Ok(())
     }
 
-    // This is a bit different and weird compared to the other print functions.
-    // The reason is to reduce code duplication, since we also use mkvmerge
-    // for muxing.
-    fn print_mkvmerge_args(&self) {
-        let output = format!("{:?}", self.build_mkvmerge_command(Path::new("NUL"), None));
-        println!(
-            "{}",
-            output
-                .replace('"', "")
-                .trim_start_matches("mkvmerge -o NUL ")
-                .trim_end_matches(" NUL")
-        );
+    fn print_rav1e_args(&self) -> Result<()> {
+        println!("{}", self.rav1e_args()?);
+        Ok(())
     }
 
-    fn build_mkvmerge_command(&self, target: &Path, chapters: Option<&Path>) -> Command {
-        let mut command = Command::new("mkvpropedit");
-        command.arg("-e").arg("track:v1");
+    fn rav1e_args(&self) -> Result<String> {
+        let mut args = Vec::new();
         if let Some(ref basic) = self.basic {
-            command
-                .arg("-s")
-                .arg(format!(
-                    "colour-range={}",
-                    color_range_to_mkvedit_prop(basic.range)
-                ))
-                .arg("-s")
-                .arg(format!(
-                    "colour-transfer-characteristics={}",
-                    basic.transfer
-                ))
-                .arg("-s")
-                .arg(format!("colour-primaries={}", basic.primaries))
-                .arg("-s")
-                .arg(format!("colour-matrix-coefficients={}", basic.matrix));
-        }
-        if let Some(ref hdr_data) = self.hdr {
-            if hdr_data.max_content_light > 0 {
-                command
-                    .arg("-s")
-                    .arg(format!("max-content-light={}", hdr_data.max_content_light));
+            if let Some(range) = basic.range {
+                args.push(format!("--range {}", range.rav1e_name()?));
             }
-            if hdr_data.max_frame_light > 0 {
-                command
-                    .arg("-s")
-                    .arg(format!("max-frame-light={}", hdr_data.max_frame_light));
+            if let Some(primaries) = basic.primaries {
+                args.push(format!("--primaries {}", primaries.rav1e_name()?));
             }
-            command
-                .arg("-s")
-                .arg(format!("max-luminance={}", hdr_data.max_luma))
-                .arg("-s")
-                .arg(format!("min-luminance={:.4}", hdr_data.min_luma));
+            if let Some(transfer) = basic.transfer {
+                args.push(format!("--transfer {}", transfer.rav1e_name()?));
+            }
+            if let Some(matrix) = basic.matrix {
+                args.push(format!("--matrix {}", matrix.rav1e_name()?));
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            args.push(format!(
+                "--content-light {},{}",
+                hdr_data.max_content_light, hdr_data.max_frame_light
+            ));
             if let Some(ref color_coords) = hdr_data.color_coords {
-                command
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-red-x={:.5}",
-                        color_coords.red.0
-                    ))
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-red-y={:.5}",
-                        color_coords.red.1
-                    ))
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-green-x={:.5}",
-                        color_coords.green.0
-                    ))
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-green-y={:.5}",
-                        color_coords.green.1
-                    ))
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-blue-x={:.5}",
-                        color_coords.blue.0
-                    ))
-                    .arg("-s")
-                    .arg(format!(
-                        "chromaticity-coordinates-blue-y={:.5}",
-                        color_coords.blue.1
+                args.push(format!(
+                    "--mastering-display {}",
+                    shell_quote(&format_master_display(
+                        color_coords,
+                        hdr_data.max_luma,
+                        hdr_data.min_luma
                     ))
-                    .arg("-s")
-                    .arg(format!("white-coordinates-x={:.5}", color_coords.white.0))
-                    .arg("-s")
-                    .arg(format!("white-coordinates-y={:.5}", color_coords.white.1));
+                ));
             }
         }
-        if let Some(chapters) = chapters {
-            command.arg("-c").arg(chapters);
-        }
-        command.arg(target);
-        command
+        Ok(args.join(" "))
     }
-}
-
-fn format_master_display(coords: &ColorCoordinates, max_luma: u32, min_luma: f64) -> String {
-    format!(
-        "G({},{})B({},{})R({},{})WP({},{})L({},{})",
-        (coords.green.0 * 50000.).round() as u32,
-        (coords.green.1 * 50000.).round() as u32,
-        (coords.blue.0 * 50000.).round() as u32,
-        (coords.blue.1 * 50000.).round() as u32,
-        (coords.red.0 * 50000.).round() as u32,
-        (coords.red.1 * 50000.).round() as u32,
-        (coords.white.0 * 50000.).round() as u32,
-        (coords.white.1 * 50000.).round() as u32,
-        max_luma * 50000,
-        (min_luma * 50000.).round() as u32,
-    )
-}
 
-pub fn extract_chapters(input: &Path) -> Option<PathBuf> {
-    let output = input.with_extension("hdrcp_chapters.xml");
-    let result = Command::new("mkvextract")
-        .arg(input)
-        .arg("chapters")
+    // aomenc has no options for HDR mastering metadata, so that
+    // has to be added afterwards, e.g. by `hdrcopier copy`.
+    fn print_aomenc_args(&self) -> Result<()> {
+        if let Some(ref basic) = self.basic {
+            let mut args = Vec::new();
+            if let Some(primaries) = basic.primaries {
+                args.push(format!("--color-primaries={}", primaries.aom_name()?));
+            }
+            if let Some(transfer) = basic.transfer {
+                args.push(format!(
+                    "--transfer-characteristics={}",
+                    transfer.aom_name()?
+                ));
+            }
+            if let Some(matrix) = basic.matrix {
+                args.push(format!("--matrix-coefficients={}", matrix.aom_name()?));
+            }
+            if let Some(range) = basic.range {
+                args.push(format!("--color-range={}", range.to_av1()?));
+            }
+            println!("{}", args.join(" "));
+        }
+        if self.hdr.is_some() {
+            log::warn!(
+                "aomenc does not support HDR mastering display or content light metadata; \
+                 use `hdrcopier copy` to add it to the encoded file"
+            );
+        }
+        Ok(())
+    }
+
+    // Matches the labels, order, and raw values shown in the header editor
+    // of mkvtoolnix-gui, so that the two can be compared side by side.
+    fn print_mkvtoolnix_gui_format(&self) {
+        if let Some(ref basic) = self.basic {
+            if let Some(matrix) = basic.matrix {
+                println!("Colour matrix coefficients: {}", matrix.as_u8());
+            }
+            if let Some(range) = basic.range {
+                println!("Colour range: {}", range.to_mkvedit_prop());
+            }
+            if let Some(transfer) = basic.transfer {
+                println!("Colour transfer characteristics: {}", transfer.as_u8());
+            }
+            if let Some(primaries) = basic.primaries {
+                println!("Colour primaries: {}", primaries.as_u8());
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            println!("Maximum content light: {}", hdr_data.max_content_light);
+            println!("Maximum frame light: {}", hdr_data.max_frame_light);
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                println!("Red colour coordinate x: {}", color_coords.red.0);
+                println!("Red colour coordinate y: {}", color_coords.red.1);
+                println!("Green colour coordinate x: {}", color_coords.green.0);
+                println!("Green colour coordinate y: {}", color_coords.green.1);
+                println!("Blue colour coordinate x: {}", color_coords.blue.0);
+                println!("Blue colour coordinate y: {}", color_coords.blue.1);
+                println!("White colour coordinate x: {}", color_coords.white.0);
+                println!("White colour coordinate y: {}", color_coords.white.1);
+            }
+            println!("Maximum luminance: {}", hdr_data.max_luma);
+            println!("Minimum luminance: {}", hdr_data.min_luma);
+        }
+    }
+
+    // Uses the field names from SMPTE ST 2086 for the mastering display,
+    // and from CTA-861.3 for the content light levels, which is what
+    // grading tools consuming this data will expect.
+    fn print_st2086_json(&self) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(&self.st2086_json())?);
+        Ok(())
+    }
+
+    fn st2086_json(&self) -> serde_json::Value {
+        let hdr_data = match self.hdr {
+            Some(ref hdr_data) => hdr_data,
+            None => {
+                return json!({
+                    "mastering_display": null,
+                    "content_light_level": null,
+                })
+            }
+        };
+        let point = |(x, y): (f64, f64)| json!({ "x": x, "y": y });
+        let (display_primaries, white_point) = match hdr_data.color_coords {
+            Some(ref color_coords) => (
+                json!({
+                    "red": point(color_coords.red),
+                    "green": point(color_coords.green),
+                    "blue": point(color_coords.blue),
+                }),
+                point(color_coords.white),
+            ),
+            None => (serde_json::Value::Null, serde_json::Value::Null),
+        };
+        json!({
+            "mastering_display": {
+                "display_primaries": display_primaries,
+                "white_point_chromaticity": white_point,
+                "max_display_mastering_luminance": hdr_data.max_luma,
+                "min_display_mastering_luminance": hdr_data.min_luma,
+            },
+            "content_light_level": {
+                "max_cll": hdr_data.max_content_light,
+                "max_fall": hdr_data.max_frame_light,
+            },
+        })
+    }
+
+    // Prints `KEY=value` lines which can be `eval`ed by a POSIX shell.
+    // Values are the raw numeric codes, not the human-readable names.
+    fn print_env_vars(&self) {
+        if let Some(ref basic) = self.basic {
+            if let Some(matrix) = basic.matrix {
+                println!("HDR_MATRIX={}", matrix.as_u8());
+            }
+            if let Some(range) = basic.range {
+                println!("HDR_RANGE={}", range.as_u8());
+            }
+            if let Some(transfer) = basic.transfer {
+                println!("HDR_TRANSFER={}", transfer.as_u8());
+            }
+            if let Some(primaries) = basic.primaries {
+                println!("HDR_PRIMARIES={}", primaries.as_u8());
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            println!("HDR_MAX_CLL={}", hdr_data.max_content_light);
+            println!("HDR_MAX_FALL={}", hdr_data.max_frame_light);
+            println!("HDR_MAX_LUMINANCE={}", hdr_data.max_luma);
+            println!("HDR_MIN_LUMINANCE={}", hdr_data.min_luma);
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                println!(
+                    "HDR_MASTER_DISPLAY={}",
+                    shell_quote(&format_master_display(
+                        color_coords,
+                        hdr_data.max_luma,
+                        hdr_data.min_luma
+                    ))
+                );
+            }
+        }
+    }
+
+    // Prints the ITU-T H.273 codes as-is, for encoders which take integers. Unlike the
+    // encoder formats, this never needs a name for a value, so it works for any file.
+    fn print_numeric_values(&self) {
+        if let Some(ref basic) = self.basic {
+            if let Some(primaries) = basic.primaries {
+                println!("primaries={}", primaries.as_u8());
+            }
+            if let Some(transfer) = basic.transfer {
+                println!("transfer={}", transfer.as_u8());
+            }
+            if let Some(matrix) = basic.matrix {
+                println!("matrix={}", matrix.as_u8());
+            }
+            // H.273 uses a flag which is set for full range, the opposite of how we store it
+            if let Some(range) = basic.range {
+                println!("full-range={}", u8::from(range == ColorRange::Full));
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            println!(
+                "max-cll={},{}",
+                hdr_data.max_content_light, hdr_data.max_frame_light
+            );
+            println!("max-luma={}", hdr_data.max_luma);
+            println!("min-luma={}", hdr_data.min_luma);
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                println!(
+                    "master-display={}",
+                    format_master_display(color_coords, hdr_data.max_luma, hdr_data.min_luma)
+                );
+            }
+        }
+    }
+
+    // Prints a VapourSynth call which tags a clip with the H.273 codes, for filtering scripts.
+    // Unspecified values and ones without a name in H.273 are left out, since VapourSynth
+    // treats a missing prop as unspecified. `_ColorRange` is 0 for full, the same as ours.
+    fn print_vapoursynth_args(&self) -> Result<()> {
+        let basic = match self.basic {
+            Some(ref basic) => basic,
+            None => anyhow::bail!("File has no colour metadata"),
+        };
+        let mut props = Vec::new();
+        if let Some(matrix) = basic
+            .matrix
+            .filter(|&matrix| matrix != MatrixCoefficients::Unspecified && matrix.name().is_ok())
+        {
+            props.push(format!("_Matrix={}", matrix.as_u8()));
+        }
+        if let Some(transfer) = basic.transfer.filter(|&transfer| {
+            transfer != TransferCharacteristics::Unspecified && transfer.name().is_ok()
+        }) {
+            props.push(format!("_Transfer={}", transfer.as_u8()));
+        }
+        if let Some(primaries) = basic.primaries.filter(|&primaries| {
+            primaries != ColorPrimaries::Unspecified && primaries.name().is_ok()
+        }) {
+            props.push(format!("_Primaries={}", primaries.as_u8()));
+        }
+        if let Some(range) = basic.range.filter(|range| range.name().is_ok()) {
+            props.push(format!("_ColorRange={}", range.as_u8()));
+        }
+        if props.is_empty() {
+            anyhow::bail!("File has no known colour values");
+        }
+        println!("clip = core.std.SetFrameProps(clip, {})", props.join(", "));
+        Ok(())
+    }
+
+    // Prints all of the metadata we parsed, using the same field names as the structs
+    fn print_json(&self) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(self)?);
+        Ok(())
+    }
+
+    // Builds an ffmpeg filtergraph which tonemaps the HDR input down to BT.709 SDR,
+    // for quick previews. zscale can read the input properties from the frames,
+    // but they are frequently missing or wrong, so we fill in what we know.
+    fn print_tonemap_filter(&self) {
+        println!("{}", self.tonemap_filter());
+    }
+
+    fn tonemap_filter(&self) -> String {
+        let mut input = String::new();
+        if let Some(ref basic) = self.basic {
+            // Unspecified values, and ones which zscale has no name for,
+            // are left for zscale to figure out from the frame
+            if let Some(transfer) = basic
+                .transfer
+                .filter(|&transfer| transfer != TransferCharacteristics::Unspecified)
+                .and_then(|transfer| transfer.zscale_name().ok())
+            {
+                input.push_str(&format!("tin={}:", transfer));
+            }
+            if let Some(primaries) = basic
+                .primaries
+                .filter(|&primaries| primaries != ColorPrimaries::Unspecified)
+                .and_then(|primaries| primaries.zscale_name().ok())
+            {
+                input.push_str(&format!("pin={}:", primaries));
+            }
+            if let Some(matrix) = basic
+                .matrix
+                .filter(|&matrix| matrix != MatrixCoefficients::Unspecified)
+                .and_then(|matrix| matrix.zscale_name().ok())
+            {
+                input.push_str(&format!("min={}:", matrix));
+            }
+            if let Some(range) = basic.range.and_then(|range| range.zscale_name().ok()) {
+                input.push_str(&format!("rin={}:", range));
+            }
+        }
+        format!(
+            "zscale={}t=linear:npl=100,format=gbrpf32le,tonemap=hable,zscale=t=709:m=709:\
+             p=709:r=limited,format=yuv420p",
+            input
+        )
+    }
+
+    // This is a bit different and weird compared to the other print functions.
+    // The reason is to reduce code duplication, since we also use mkvmerge
+    // for muxing.
+    // Prints only the track edit arguments, without the program name or the target,
+    // so they can be used with any file
+    fn print_mkvmerge_args(&self) {
+        println!("{}", self.mkvmerge_args());
+    }
+
+    fn mkvmerge_args(&self) -> String {
+        let command = self.build_mkvmerge_command(Path::new(""), 1, None, false, &[]);
+        // The target is always the last argument
+        let args: Vec<_> = command.get_args().collect();
+        quote_args(&args[..args.len() - 1])
+    }
+
+    // Lists the mkvpropedit properties we would set on the target, and their values
+    fn mkvpropedit_properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = Vec::new();
+        if let Some(ref basic) = self.basic {
+            if let Some(range) = basic.range {
+                properties.push(("colour-range", range.to_mkvedit_prop().to_string()));
+            }
+            if let Some(transfer) = basic.transfer {
+                properties.push((
+                    "colour-transfer-characteristics",
+                    transfer.as_u8().to_string(),
+                ));
+            }
+            if let Some(primaries) = basic.primaries {
+                properties.push(("colour-primaries", primaries.as_u8().to_string()));
+            }
+            if let Some(matrix) = basic.matrix {
+                properties.push(("colour-matrix-coefficients", matrix.as_u8().to_string()));
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            if hdr_data.max_content_light > 0 {
+                properties.push(("max-content-light", hdr_data.max_content_light.to_string()));
+            }
+            if hdr_data.max_frame_light > 0 {
+                properties.push(("max-frame-light", hdr_data.max_frame_light.to_string()));
+            }
+            if hdr_data.has_mastering_display() {
+                properties.push(("max-luminance", hdr_data.max_luma.to_string()));
+                properties.push(("min-luminance", format!("{:.4}", hdr_data.min_luma)));
+            }
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                let coords = [
+                    ("chromaticity-coordinates-red-x", color_coords.red.0),
+                    ("chromaticity-coordinates-red-y", color_coords.red.1),
+                    ("chromaticity-coordinates-green-x", color_coords.green.0),
+                    ("chromaticity-coordinates-green-y", color_coords.green.1),
+                    ("chromaticity-coordinates-blue-x", color_coords.blue.0),
+                    ("chromaticity-coordinates-blue-y", color_coords.blue.1),
+                    ("white-coordinates-x", color_coords.white.0),
+                    ("white-coordinates-y", color_coords.white.1),
+                ];
+                for (property, value) in coords {
+                    properties.push((property, format!("{:.5}", value)));
+                }
+            }
+        }
+        if let Some(ref chroma_siting) = self.chroma_siting {
+            if chroma_siting.horizontal != 0 {
+                properties.push((
+                    "chroma-siting-horizontal",
+                    chroma_siting.horizontal.to_string(),
+                ));
+            }
+            if chroma_siting.vertical != 0 {
+                properties.push(("chroma-siting-vertical", chroma_siting.vertical.to_string()));
+            }
+        }
+        if let Some(ref format) = self.color_format {
+            let values = [
+                ("colour-bits-per-channel", format.bits_per_channel),
+                (
+                    "chroma-subsample-horizontal",
+                    format.chroma_subsample_horizontal,
+                ),
+                (
+                    "chroma-subsample-vertical",
+                    format.chroma_subsample_vertical,
+                ),
+                ("cb-subsample-horizontal", format.cb_subsample_horizontal),
+                ("cb-subsample-vertical", format.cb_subsample_vertical),
+            ];
+            for (property, value) in values {
+                if value != 0 {
+                    properties.push((property, value.to_string()));
+                }
+            }
+        }
+        if let Some(field_order) = self.field_order {
+            // Players look at the interlaced flag before the field order
+            let interlaced = if field_order == 0 { 2 } else { 1 };
+            properties.push(("flag-interlaced", interlaced.to_string()));
+            properties.push(("field-order", field_order.to_string()));
+        }
+        properties
+    }
+
+    // The mkvpropedit properties for the track name and segment title. These are kept
+    // apart from the colour properties, since the title is in a different element
+    // and `strip` shouldn't remove either of them.
+    // Every property which `apply` sets, formatted as mkvpropedit takes them
+    fn container_properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = self.mkvpropedit_properties();
+        properties.extend(self.title_properties());
+        properties
+    }
+
+    fn title_properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = Vec::new();
+        if let Some(ref track_name) = self.track_name {
+            properties.push(("name", track_name.clone()));
+        }
+        if let Some(ref title) = self.title {
+            properties.push(("title", title.clone()));
+        }
+        properties
+    }
+
+    // Replaces any of the parsed values with the ones given by the user
+    pub fn apply_overrides(&mut self, overrides: &MetadataOverrides) -> Result<()> {
+        validate_overrides(overrides)?;
+
+        let has_basic = overrides.primaries.is_some()
+            || overrides.transfer.is_some()
+            || overrides.matrix.is_some()
+            || overrides.range.is_some();
+        if has_basic {
+            // Only the values which were given are set, so the rest are left as they are
+            // on the target instead of being replaced with made up ones
+            let basic = self.basic.get_or_insert_with(BasicMetadata::default);
+            basic.primaries = overrides.primaries.or(basic.primaries);
+            basic.transfer = overrides.transfer.or(basic.transfer);
+            basic.matrix = overrides.matrix.or(basic.matrix);
+            basic.range = overrides.range.or(basic.range);
+        }
+
+        if overrides.max_cll.is_some()
+            || overrides.master_display.is_some()
+            || overrides.max_luma.is_some()
+            || overrides.min_luma.is_some()
+        {
+            let hdr = self.hdr.get_or_insert_with(HdrMetadata::default);
+            if let Some((max_content_light, max_frame_light)) = overrides.max_cll {
+                hdr.max_content_light = max_content_light;
+                hdr.max_frame_light = max_frame_light;
+            }
+            if let Some(ref master_display) = overrides.master_display {
+                let master_display = parse_master_display(master_display)?;
+                hdr.color_coords = master_display.color_coords;
+                hdr.max_luma = master_display.max_luma;
+                hdr.min_luma = master_display.min_luma;
+            }
+            if overrides.max_luma.is_some() || overrides.min_luma.is_some() {
+                if let Some(max_luma) = overrides.max_luma {
+                    hdr.max_luma = max_luma;
+                }
+                if let Some(min_luma) = overrides.min_luma {
+                    hdr.min_luma = min_luma;
+                }
+                // Only one may have been given, so check it against the other's parsed value
+                if hdr.max_luma <= hdr.min_luma {
+                    anyhow::bail!(
+                        "Maximum luminance ({}) must be greater than minimum luminance ({})",
+                        hdr.max_luma,
+                        hdr.min_luma
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Like `apply`, but writes a new file to `output` with mkvmerge,
+    // leaving the target untouched.
+    pub fn remux(
+        &self,
+        target: &Path,
+        track: u64,
+        output: &Path,
+        chapters: Option<&Path>,
+        extra_args: &[String],
+        dry_run: bool,
+    ) -> Result<String> {
+        let track = find_video_track(target, track)?;
+        let mut command = tool_command("mkvmerge");
+        command.arg("-o").arg(output);
+        command.args(self.mkvmerge_track_options(track));
+        if let Some(ref track_name) = self.track_name {
+            command
+                .arg("--track-name")
+                .arg(format!("{}:{}", track, track_name));
+        }
+        if let Some(ref title) = self.title {
+            command.arg("--title").arg(title);
+        }
+        if let Some(chapters) = chapters {
+            command.arg("--chapters").arg(chapters);
+        }
+        command.args(extra_args);
+        command.arg(target);
+        let formatted = format_command(&command);
+        if dry_run {
+            println!("{}", formatted);
+            return Ok(formatted);
+        }
+        log_command(&command);
+        let status = command.tool_run()?;
+        ToolOutput::global().invalidate(output);
+        // mkvmerge exits with 1 for warnings, which still produces a usable file
+        if !matches!(status.code(), Some(0) | Some(1)) {
+            anyhow::bail!("Failed to mux metadata");
+        }
+        Ok(formatted)
+    }
+
+    // The mkvmerge equivalents of `mkvpropedit_properties`, for the given track
+    fn mkvmerge_track_options(&self, track: u64) -> Vec<String> {
+        let mut options = Vec::new();
+        let mut push = |option: &str, value: String| {
+            options.push(option.to_string());
+            options.push(format!("{}:{}", track, value));
+        };
+        if let Some(ref basic) = self.basic {
+            if let Some(range) = basic.range {
+                push("--colour-range", range.to_mkvedit_prop().to_string());
+            }
+            if let Some(transfer) = basic.transfer {
+                push(
+                    "--colour-transfer-characteristics",
+                    transfer.as_u8().to_string(),
+                );
+            }
+            if let Some(primaries) = basic.primaries {
+                push("--colour-primaries", primaries.as_u8().to_string());
+            }
+            if let Some(matrix) = basic.matrix {
+                push("--colour-matrix-coefficients", matrix.as_u8().to_string());
+            }
+        }
+        if let Some(ref hdr_data) = self.hdr {
+            if hdr_data.max_content_light > 0 {
+                push(
+                    "--max-content-light",
+                    hdr_data.max_content_light.to_string(),
+                );
+            }
+            if hdr_data.max_frame_light > 0 {
+                push("--max-frame-light", hdr_data.max_frame_light.to_string());
+            }
+            if hdr_data.has_mastering_display() {
+                push("--max-luminance", hdr_data.max_luma.to_string());
+                push("--min-luminance", format!("{:.4}", hdr_data.min_luma));
+            }
+            if let Some(ref color_coords) = hdr_data.color_coords {
+                push(
+                    "--chromaticity-coordinates",
+                    format!(
+                        "{:.5},{:.5},{:.5},{:.5},{:.5},{:.5}",
+                        color_coords.red.0,
+                        color_coords.red.1,
+                        color_coords.green.0,
+                        color_coords.green.1,
+                        color_coords.blue.0,
+                        color_coords.blue.1
+                    ),
+                );
+                push(
+                    "--white-colour-coordinates",
+                    format!("{:.5},{:.5}", color_coords.white.0, color_coords.white.1),
+                );
+            }
+        }
+        if let Some(ref chroma_siting) = self.chroma_siting {
+            push(
+                "--chroma-siting",
+                format!("{},{}", chroma_siting.horizontal, chroma_siting.vertical),
+            );
+        }
+        if let Some(ref format) = self.color_format {
+            if format.bits_per_channel != 0 {
+                push(
+                    "--colour-bits-per-channel",
+                    format.bits_per_channel.to_string(),
+                );
+            }
+            // mkvmerge always sets both directions, so they're skipped only if both are zero
+            if format.chroma_subsample_horizontal != 0 || format.chroma_subsample_vertical != 0 {
+                push(
+                    "--chroma-subsample",
+                    format!(
+                        "{},{}",
+                        format.chroma_subsample_horizontal, format.chroma_subsample_vertical
+                    ),
+                );
+            }
+            if format.cb_subsample_horizontal != 0 || format.cb_subsample_vertical != 0 {
+                push(
+                    "--cb-subsample",
+                    format!(
+                        "{},{}",
+                        format.cb_subsample_horizontal, format.cb_subsample_vertical
+                    ),
+                );
+            }
+        }
+        if let Some(field_order) = self.field_order {
+            push("--field-order", field_order.to_string());
+        }
+        options
+    }
+
+    // Describes what `apply` would change on a target which currently has
+    // the `existing` metadata, without changing anything.
+    pub fn print_plan(&self, existing: &Metadata) {
+        for line in self.plan(existing) {
+            println!("{}", line);
+        }
+    }
+
+    // `apply` sets every property it has a value for, even one which already has
+    // that value, and leaves the target's other properties alone
+    fn plan(&self, existing: &Metadata) -> Vec<String> {
+        let existing = existing.container_properties();
+        let properties = self.container_properties();
+        let mut lines = Vec::new();
+        for (property, value) in &properties {
+            lines.push(match existing.iter().find(|(name, _)| name == property) {
+                Some((_, old)) if old == value => format!("{}: {} (unchanged)", property, value),
+                Some((_, old)) => format!("{}: {}→{} (change)", property, old, value),
+                None => format!("{}: unset→{} (set)", property, value),
+            });
+        }
+        for (property, old) in &existing {
+            if !properties.iter().any(|(name, _)| name == property) {
+                lines.push(format!("{}: {} (kept, skip)", property, old));
+            }
+        }
+        lines
+    }
+
+    // Prints each field of this and the `other` metadata side by side,
+    // marking the ones which differ. Returns whether all of the fields match.
+    pub fn print_diff(&self, other: &Metadata) -> bool {
+        let mut all_match = true;
+        for (field, left, right, matches) in self.compare_fields(other) {
+            all_match &= matches;
+            println!(
+                "{:<28} {:>12} {:>12}{}",
+                format!("{}:", field),
+                left.map_or_else(|| "unset".to_string(), |value| value.to_string()),
+                right.map_or_else(|| "unset".to_string(), |value| value.to_string()),
+                if matches { "" } else { "  (differs)" }
+            );
+        }
+        all_match
+    }
+
+    // Lists the fields which are set in this metadata, but which have
+    // a different value or are missing in the `actual` metadata,
+    // e.g. because they were not applied to a file.
+    pub fn unapplied_fields(&self, actual: &Metadata) -> Vec<&'static str> {
+        self.compare_fields(actual)
+            .into_iter()
+            .filter(|&(_, intended, _, matches)| intended.is_some() && !matches)
+            .map(|(field, ..)| field)
+            .collect()
+    }
+
+    // Pairs up each field of this and the `other` metadata, along with whether they match
+    fn compare_fields(
+        &self,
+        other: &Metadata,
+    ) -> Vec<(&'static str, Option<f64>, Option<f64>, bool)> {
+        self.comparable_fields()
+            .into_iter()
+            .zip(other.comparable_fields())
+            .map(|((field, left, epsilon), (_, right, _))| {
+                let matches = match (left, right) {
+                    (Some(left), Some(right)) => (left - right).abs() <= epsilon,
+                    (None, None) => true,
+                    _ => false,
+                };
+                (field, left, right, matches)
+            })
+            .collect()
+    }
+
+    // Lists every field as a float, along with how much it may differ by and still be equal
+    fn comparable_fields(&self) -> Vec<(&'static str, Option<f64>, f64)> {
+        let basic = self.basic.as_ref();
+        let hdr = self.hdr.as_ref();
+        let coords = hdr.and_then(|hdr| hdr.color_coords.as_ref());
+        let mastering = hdr.filter(|hdr| hdr.has_mastering_display());
+        // Unspecified siting is never written, so it's the same as unset
+        let siting = |axis: fn(&ChromaSiting) -> u8| {
+            self.chroma_siting
+                .as_ref()
+                .map(axis)
+                .filter(|&value| value != 0)
+                .map(f64::from)
+        };
+        let format = |field: fn(&ColorFormat) -> u8| {
+            self.color_format
+                .as_ref()
+                .map(field)
+                .filter(|&value| value != 0)
+                .map(f64::from)
+        };
+        vec![
+            (
+                "Color Range",
+                basic
+                    .and_then(|basic| basic.range)
+                    .map(|range| range.as_u8() as f64),
+                0.,
+            ),
+            (
+                "Color Primaries",
+                basic
+                    .and_then(|basic| basic.primaries)
+                    .map(|primaries| primaries.as_u8() as f64),
+                0.,
+            ),
+            (
+                "Transfer Characteristics",
+                basic
+                    .and_then(|basic| basic.transfer)
+                    .map(|transfer| transfer.as_u8() as f64),
+                0.,
+            ),
+            (
+                "Matrix Coefficients",
+                basic
+                    .and_then(|basic| basic.matrix)
+                    .map(|matrix| matrix.as_u8() as f64),
+                0.,
+            ),
+            (
+                "Max Content Light Level",
+                hdr.map(|hdr| hdr.max_content_light as f64),
+                0.,
+            ),
+            (
+                "Max Frame-Average Light",
+                hdr.map(|hdr| hdr.max_frame_light as f64),
+                0.,
+            ),
+            (
+                "Maximum Luminance",
+                mastering.map(|hdr| hdr.max_luma),
+                LUMINANCE_EPSILON,
+            ),
+            (
+                "Minimum Luminance",
+                mastering.map(|hdr| hdr.min_luma),
+                LUMINANCE_EPSILON,
+            ),
+            (
+                "Red Coordinate x",
+                coords.map(|coords| coords.red.0),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Red Coordinate y",
+                coords.map(|coords| coords.red.1),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Green Coordinate x",
+                coords.map(|coords| coords.green.0),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Green Coordinate y",
+                coords.map(|coords| coords.green.1),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Blue Coordinate x",
+                coords.map(|coords| coords.blue.0),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Blue Coordinate y",
+                coords.map(|coords| coords.blue.1),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "White Point Coordinate x",
+                coords.map(|coords| coords.white.0),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "White Point Coordinate y",
+                coords.map(|coords| coords.white.1),
+                COORDINATE_EPSILON,
+            ),
+            (
+                "Horizontal Chroma Siting",
+                siting(|siting| siting.horizontal),
+                0.,
+            ),
+            (
+                "Vertical Chroma Siting",
+                siting(|siting| siting.vertical),
+                0.,
+            ),
+            (
+                "Bits Per Channel",
+                format(|format| format.bits_per_channel),
+                0.,
+            ),
+            (
+                "Horizontal Chroma Subsampling",
+                format(|format| format.chroma_subsample_horizontal),
+                0.,
+            ),
+            (
+                "Vertical Chroma Subsampling",
+                format(|format| format.chroma_subsample_vertical),
+                0.,
+            ),
+            (
+                "Horizontal Cb Subsampling",
+                format(|format| format.cb_subsample_horizontal),
+                0.,
+            ),
+            (
+                "Vertical Cb Subsampling",
+                format(|format| format.cb_subsample_vertical),
+                0.,
+            ),
+            ("Field Order", self.field_order.map(f64::from), 0.),
+        ]
+    }
+
+    fn build_mkvmerge_command(
+        &self,
+        target: &Path,
+        track: u64,
+        chapters: Option<&Path>,
+        strip_existing: bool,
+        extra_args: &[String],
+    ) -> Command {
+        let mut command = tool_command("mkvpropedit");
+        command.arg("-e").arg(format!("track:v{}", track));
+        if strip_existing {
+            for property in COLOUR_PROPERTIES {
+                command.arg("-d").arg(property);
+            }
+        }
+        for (property, value) in self.mkvpropedit_properties() {
+            command.arg("-s").arg(format!("{}={}", property, value));
+        }
+        if let Some(ref track_name) = self.track_name {
+            command.arg("-s").arg(format!("name={}", track_name));
+        }
+        if let Some(ref title) = self.title {
+            command
+                .arg("-e")
+                .arg("info")
+                .arg("-s")
+                .arg(format!("title={}", title));
+        }
+        if let Some(chapters) = chapters {
+            command.arg("-c").arg(chapters);
+        }
+        command.args(extra_args);
+        command.arg(target);
+        command
+    }
+}
+
+// Raw video streams and images are recognized by their extension,
+// since most raw streams have no magic bytes.
+//
+// Each of the raw parsers only reads as far into the file as its headers should go,
+// so a file which is corrupt or isn't what its extension says can't make it read the
+// whole thing. They return `None` when they find no colour metadata, in which case
+// mediainfo and ffprobe are relied on instead.
+fn parse_native(input: &Path, from_bitstream: bool, track: u64) -> Result<Option<Metadata>> {
+    let extension = input
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let is_raw = matches!(
+        extension.as_deref(),
+        Some("hevc" | "h265" | "265" | "obu" | "ivf" | "png" | "exr")
+    );
+    if is_raw && track != 1 {
+        anyhow::bail!("{:?} only has one video track", input);
+    }
+    match extension.as_deref() {
+        Some("hevc" | "h265" | "265") => parse_hevc(input),
+        Some("obu" | "ivf") => parse_av1(input),
+        Some("png") => parse_png(input),
+        Some("exr") => parse_exr(input),
+        // Matroska headers aren't part of the video stream
+        _ if from_bitstream => Ok(None),
+        _ => parse_matroska(input, track),
+    }
+}
+
+fn run_mkvpropedit(mut command: Command, target: &Path, dry_run: bool) -> Result<String> {
+    let formatted = format_command(&command);
+    if dry_run {
+        println!("{}", formatted);
+        return Ok(formatted);
+    }
+    log_command(&command);
+    let output = command.tool_run_captured()?;
+    // Even a failed run may have partially modified the file
+    ToolOutput::global().invalidate(target);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // mkvpropedit reports errors on stdout, and doesn't always exit with an error for them
+    let errors: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Error:"))
+        .map(str::trim)
+        .collect();
+    if !output.status.success() || !errors.is_empty() {
+        if errors.is_empty() {
+            anyhow::bail!("Failed to mux metadata");
+        }
+        anyhow::bail!("Failed to mux metadata: {}", errors.join("; "));
+    }
+    Ok(formatted)
+}
+
+// Luminances are in units of 0.0001 cd/m², unlike the chromaticities
+fn format_master_display(coords: &ColorCoordinates, max_luma: f64, min_luma: f64) -> String {
+    format!(
+        "G({},{})B({},{})R({},{})WP({},{})L({},{})",
+        chromaticity_to_units(coords.green.0),
+        chromaticity_to_units(coords.green.1),
+        chromaticity_to_units(coords.blue.0),
+        chromaticity_to_units(coords.blue.1),
+        chromaticity_to_units(coords.red.0),
+        chromaticity_to_units(coords.red.1),
+        chromaticity_to_units(coords.white.0),
+        chromaticity_to_units(coords.white.1),
+        (max_luma * 10000.).round() as u32,
+        (min_luma * 10000.).round() as u32,
+    )
+}
+
+// Formats a command so that it can be pasted into the user's shell
+fn format_command(command: &Command) -> String {
+    let args: Vec<_> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .collect();
+    quote_args(&args)
+}
+
+fn quote_args(args: &[&OsStr]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// x265 has no name for some newer values, e.g. EBU 3213-E primaries. Rather than
+// printing nothing, those fall back to `unknown`, so only one value has to be fixed by hand.
+fn x265_value_or_unknown(
+    value: Result<&'static str, UnknownValueError>,
+) -> Result<&'static str, UnknownValueError> {
+    match value {
+        Err(e) if e.is_unsupported() => {
+            log::warn!("{}, so `unknown` was used instead", e);
+            Ok("unknown")
+        }
+        value => value,
+    }
+}
+
+// Quotes a value so that the shell will treat it as a single literal word.
+// Windows shells don't understand single quotes, so use double quotes there.
+fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./,:=+@%".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return value.to_string();
+    }
+    if cfg!(windows) {
+        return format!("\"{}\"", value.replace('"', r#"\""#));
+    }
+    format!("'{}'", value.replace('\'', r#"'\''"#))
+}
+
+// Master display strings store chromaticity coordinates as integers
+// in increments of 0.00002, i.e. the coordinate multiplied by 50000.
+//
+// We always round to the nearest integer, never truncate. Matroska stores
+// the coordinates as 32-bit floats, so a coordinate written as 15999/50000
+// comes back from mkvinfo as 0.31997999... and would be truncated to 15998.
+// Rounding means any value that was originally written from the integer
+// form will convert back to exactly that integer.
+//
+// As a reference point, D65 is (0.3127, 0.3290), which is WP(15635,16450).
+// Be aware that many masters in the wild use WP(15634,16450) instead,
+// which is (0.31268, 0.329). These are both preserved as-is.
+pub(crate) fn chromaticity_to_units(value: f64) -> u32 {
+    (value * 50000.).round() as u32
+}
+
+pub(crate) fn chromaticity_from_units(value: u32) -> f64 {
+    value as f64 / 50000.
+}
+
+// Merges what one of the tools found into `data`, noting which values came from it
+fn merge_tool(data: Metadata, info: Metadata, tool: Tool) -> Metadata {
+    if data.basic.is_none() && info.basic.is_some() {
+        log::debug!("Read colour values with {}", tool.name());
+    }
+    if data.takes_hdr_from(&info) {
+        log::debug!("Read HDR metadata with {}", tool.name());
+    }
+    data.merge(info)
+}
+
+// Runs one of the metadata tools and its parser on their own thread
+fn spawn_parser(input: &Path, tool: Tool, track: u64) -> JoinHandle<Result<Metadata>> {
+    let input = input.to_path_buf();
+    thread::spawn(move || tool.parse(&ToolOutput::global().get(tool, &input, track)?, track))
+}
+
+fn join_parser(handle: JoinHandle<Result<Metadata>>) -> Result<Metadata> {
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Metadata parser thread panicked")))
+}
+
+// Checks that each of the overridden values is one we understand,
+// so that mistakes are caught before doing any work
+pub fn validate_overrides(overrides: &MetadataOverrides) -> Result<()> {
+    if let Some(primaries) = overrides.primaries {
+        primaries.name()?;
+    }
+    if let Some(transfer) = overrides.transfer {
+        transfer.name()?;
+    }
+    if let Some(matrix) = overrides.matrix {
+        matrix.name()?;
+    }
+    if let Some(range) = overrides.range {
+        range.name()?;
+    }
+    if let Some(ref master_display) = overrides.master_display {
+        parse_master_display(master_display)?;
+    }
+    for luma in [overrides.max_luma, overrides.min_luma]
+        .into_iter()
+        .flatten()
+    {
+        if !luma.is_finite() || luma < 0.0 {
+            anyhow::bail!("Invalid luminance: {}", luma);
+        }
+    }
+    if let (Some(max_luma), Some(min_luma)) = (overrides.max_luma, overrides.min_luma) {
+        if max_luma <= min_luma {
+            anyhow::bail!(
+                "Maximum luminance ({}) must be greater than minimum luminance ({})",
+                max_luma,
+                min_luma
+            );
+        }
+    }
+    Ok(())
+}
+
+// mkvmerge's options take the track ID rather than `v1` like mkvpropedit,
+// so we have to look up the ID of the nth video track, starting from 1.
+pub(crate) fn find_video_track(input: &Path, ordinal: u64) -> Result<u64> {
+    video_track_info(input, ordinal)?["id"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("Unable to find video track {} in {:?}", ordinal, input))
+}
+
+// Returns the nth video track from `mkvmerge -J`, starting from 1
+pub(crate) fn video_track_info(input: &Path, ordinal: u64) -> Result<serde_json::Value> {
+    let result = tool_command("mkvmerge")
+        .arg("-J")
+        .arg(input)
+        .tool_output_with_timeout()?;
+    let mut info: serde_json::Value = serde_json::from_slice(&result.stdout)?;
+    info["tracks"]
+        .as_array_mut()
+        .and_then(|tracks| {
+            tracks
+                .iter_mut()
+                .filter(|track| track["type"] == "video")
+                .nth(ordinal.saturating_sub(1) as usize)
+        })
+        .map(|track| track.take())
+        .ok_or_else(|| anyhow::anyhow!("Unable to find video track {} in {:?}", ordinal, input))
+}
+
+// The chapters are written to a unique file in the system temp directory,
+// which is deleted when the returned path is dropped.
+pub fn extract_chapters(input: &Path) -> Option<TempPath> {
+    let output = temp_path("hdrcp_chapters", ".xml").ok()?;
+    let result = tool_command("mkvextract")
+        .arg(input)
+        .arg("chapters")
         .arg(&output)
-        .status();
-    if result.is_ok() && output.exists() && output.metadata().expect("File exists").len() > 0 {
+        .tool_status();
+    if let Err(ref e) = result {
+        log::warn!("{}", e);
+    }
+    if result.is_ok() && output.metadata().is_ok_and(|meta| meta.len() > 0) {
         Some(output)
     } else {
         None
     }
 }
+
+pub fn temp_path(prefix: &str, suffix: &str) -> Result<TempPath> {
+    Ok(tempfile::Builder::new()
+        .prefix(prefix)
+        .suffix(suffix)
+        .tempfile()?
+        .into_temp_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bt2020_coords() -> ColorCoordinates {
+        ColorCoordinates {
+            red: (0.708, 0.292),
+            green: (0.170, 0.797),
+            blue: (0.131, 0.046),
+            white: (0.3127, 0.3290),
+        }
+    }
+
+    #[test]
+    fn master_display_luminance_is_in_units_of_0_0001() {
+        assert_eq!(
+            format_master_display(&bt2020_coords(), 1000., 0.0001),
+            "G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)"
+        );
+        assert_eq!(
+            format_master_display(&bt2020_coords(), 4000., 0.005),
+            "G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(40000000,50)"
+        );
+    }
+
+    // The coordinates of each gamut as they're published, and the primaries they should match
+    fn gamuts() -> Vec<(ColorCoordinates, ColorPrimaries, WhitePoint)> {
+        vec![
+            (
+                ColorCoordinates {
+                    red: (0.64, 0.33),
+                    green: (0.30, 0.60),
+                    blue: (0.15, 0.06),
+                    white: (0.3127, 0.3290),
+                },
+                ColorPrimaries::Bt709,
+                WhitePoint::D65,
+            ),
+            (bt2020_coords(), ColorPrimaries::Bt2020, WhitePoint::D65),
+            (
+                ColorCoordinates {
+                    red: (0.680, 0.320),
+                    green: (0.265, 0.690),
+                    blue: (0.150, 0.060),
+                    white: (0.314, 0.351),
+                },
+                ColorPrimaries::Smpte431,
+                WhitePoint::Dci,
+            ),
+            (
+                ColorCoordinates {
+                    red: (0.680, 0.320),
+                    green: (0.265, 0.690),
+                    blue: (0.150, 0.060),
+                    white: (0.3127, 0.3290),
+                },
+                ColorPrimaries::Smpte432,
+                WhitePoint::D65,
+            ),
+        ]
+    }
+
+    fn points(coords: &ColorCoordinates) -> [(f64, f64); 4] {
+        [coords.red, coords.green, coords.blue, coords.white]
+    }
+
+    #[test]
+    fn chromaticity_units_round_trip() {
+        for (coords, primaries, _) in gamuts() {
+            for (x, y) in points(&coords) {
+                for value in [x, y] {
+                    let units = chromaticity_to_units(value);
+                    assert!(
+                        (chromaticity_from_units(units) - value).abs() < COORDINATE_EPSILON,
+                        "{:?} {} came back as {}",
+                        primaries,
+                        value,
+                        chromaticity_from_units(units)
+                    );
+                    assert_eq!(chromaticity_to_units(chromaticity_from_units(units)), units);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn d65_round_trips_to_canonical_units() {
+        assert_eq!(chromaticity_to_units(0.3127), 15635);
+        assert_eq!(chromaticity_to_units(0.3290), 16450);
+
+        // Matroska stores the coordinates as 32-bit floats, which must round, not truncate
+        assert_eq!(chromaticity_to_units(0.3127f32 as f64), 15635);
+        assert_eq!(chromaticity_to_units(0.3290f32 as f64), 16450);
+        assert_eq!(
+            chromaticity_to_units((15999. / 50000.) as f32 as f64),
+            15999
+        );
+
+        // The common off-by-one D65 is kept as-is rather than snapped to 15635
+        assert_eq!(chromaticity_to_units(chromaticity_from_units(15634)), 15634);
+
+        let parsed = parse_master_display(
+            "G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)",
+        )
+        .unwrap();
+        let white = parsed.color_coords.as_ref().unwrap().white;
+        assert_eq!(
+            (
+                chromaticity_to_units(white.0),
+                chromaticity_to_units(white.1)
+            ),
+            (15635, 16450)
+        );
+    }
+
+    #[test]
+    fn master_display_round_trip() {
+        for (coords, primaries, _) in gamuts() {
+            let formatted = format_master_display(&coords, 1000., 0.0001);
+            let parsed = parse_master_display(&formatted).unwrap();
+            let parsed_coords = parsed.color_coords.as_ref().unwrap();
+            for (expected, actual) in points(&coords).into_iter().zip(points(parsed_coords)) {
+                assert!(
+                    (expected.0 - actual.0).abs() < COORDINATE_EPSILON
+                        && (expected.1 - actual.1).abs() < COORDINATE_EPSILON,
+                    "{:?} {:?} came back as {:?}",
+                    primaries,
+                    expected,
+                    actual
+                );
+            }
+            assert_eq!(parsed.max_luma, 1000.);
+            assert_eq!(parsed.min_luma, 0.0001);
+            assert_eq!(
+                format_master_display(parsed_coords, parsed.max_luma, parsed.min_luma),
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn primaries_from_coordinates() {
+        for (coords, primaries, white_point) in gamuts() {
+            assert_eq!(coords.primaries(), primaries);
+            assert_eq!(coords.white_point(), white_point);
+
+            // They should still match after being stored in Matroska's units
+            let stored = ColorCoordinates {
+                red: (
+                    chromaticity_from_units(chromaticity_to_units(coords.red.0)),
+                    chromaticity_from_units(chromaticity_to_units(coords.red.1)),
+                ),
+                green: (
+                    chromaticity_from_units(chromaticity_to_units(coords.green.0)),
+                    chromaticity_from_units(chromaticity_to_units(coords.green.1)),
+                ),
+                blue: (
+                    chromaticity_from_units(chromaticity_to_units(coords.blue.0)),
+                    chromaticity_from_units(chromaticity_to_units(coords.blue.1)),
+                ),
+                white: (
+                    chromaticity_from_units(chromaticity_to_units(coords.white.0)),
+                    chromaticity_from_units(chromaticity_to_units(coords.white.1)),
+                ),
+            };
+            assert_eq!(stored.primaries(), primaries);
+        }
+    }
+
+    #[test]
+    fn overrides_only_set_given_values() {
+        let mut metadata = Metadata::default();
+        metadata
+            .apply_overrides(&MetadataOverrides {
+                primaries: Some(ColorPrimaries::Bt2020),
+                ..Default::default()
+            })
+            .unwrap();
+        let basic = metadata.basic.as_ref().unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, None);
+        assert_eq!(basic.matrix, None);
+        assert_eq!(basic.range, None);
+        assert_eq!(
+            metadata.mkvpropedit_properties(),
+            vec![("colour-primaries", "9".to_string())]
+        );
+    }
+
+    #[test]
+    fn overrides_keep_parsed_values() {
+        let mut metadata = Metadata {
+            basic: Some(BasicMetadata {
+                matrix: Some(MatrixCoefficients::Bt2020Ncl),
+                range: Some(ColorRange::Limited),
+                transfer: Some(TransferCharacteristics::Pq),
+                primaries: Some(ColorPrimaries::Bt709),
+            }),
+            ..Default::default()
+        };
+        metadata
+            .apply_overrides(&MetadataOverrides {
+                primaries: Some(ColorPrimaries::Bt2020),
+                ..Default::default()
+            })
+            .unwrap();
+        let basic = metadata.basic.as_ref().unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Pq));
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+        assert_eq!(basic.range, Some(ColorRange::Limited));
+    }
+
+    #[test]
+    fn temp_paths_do_not_collide() {
+        let paths: Vec<_> = (0..8)
+            .map(|_| std::thread::spawn(|| temp_path("hdrcp_chapters", ".xml").unwrap()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            assert!(path.starts_with(std::env::temp_dir()));
+            assert!(path.to_string_lossy().ends_with(".xml"));
+            assert!(path.exists());
+            assert!(paths[i + 1..].iter().all(|other| **other != **path));
+        }
+
+        // They're removed once they're no longer needed
+        let kept: Vec<_> = paths.iter().map(|path| path.to_path_buf()).collect();
+        drop(paths);
+        assert!(kept.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(shell_quote("track:v1"), "track:v1");
+        assert_eq!(shell_quote("colour-primaries=9"), "colour-primaries=9");
+
+        let mut command = Command::new("mkvpropedit");
+        command
+            .arg("-s")
+            .arg("name=It's HDR")
+            .arg("/videos/My Movie.mkv");
+        if cfg!(windows) {
+            assert_eq!(shell_quote("My Movie.mkv"), r#""My Movie.mkv""#);
+            assert_eq!(
+                format_command(&command),
+                r#"mkvpropedit -s "name=It's HDR" "/videos/My Movie.mkv""#
+            );
+        } else {
+            assert_eq!(shell_quote("My Movie.mkv"), "'My Movie.mkv'");
+            assert_eq!(shell_quote(""), "''");
+            assert_eq!(
+                format_command(&command),
+                r#"mkvpropedit -s 'name=It'\''s HDR' '/videos/My Movie.mkv'"#
+            );
+        }
+    }
+
+    fn hdr10_metadata() -> Metadata {
+        Metadata {
+            basic: Some(BasicMetadata {
+                matrix: Some(MatrixCoefficients::Bt2020Ncl),
+                range: Some(ColorRange::Limited),
+                transfer: Some(TransferCharacteristics::Pq),
+                primaries: Some(ColorPrimaries::Bt2020),
+            }),
+            hdr: Some(HdrMetadata {
+                color_coords: Some(bt2020_coords()),
+                max_luma: 1000.,
+                min_luma: 0.0001,
+                max_content_light: 944,
+                max_frame_light: 143,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mkvmerge_args_line() {
+        assert_eq!(
+            hdr10_metadata().mkvmerge_args(),
+            "-e track:v1 \
+             -s colour-range=1 \
+             -s colour-transfer-characteristics=16 \
+             -s colour-primaries=9 \
+             -s colour-matrix-coefficients=9 \
+             -s max-content-light=944 \
+             -s max-frame-light=143 \
+             -s max-luminance=1000 \
+             -s min-luminance=0.0001 \
+             -s chromaticity-coordinates-red-x=0.70800 \
+             -s chromaticity-coordinates-red-y=0.29200 \
+             -s chromaticity-coordinates-green-x=0.17000 \
+             -s chromaticity-coordinates-green-y=0.79700 \
+             -s chromaticity-coordinates-blue-x=0.13100 \
+             -s chromaticity-coordinates-blue-y=0.04600 \
+             -s white-coordinates-x=0.31270 \
+             -s white-coordinates-y=0.32900"
+        );
+
+        let metadata = Metadata {
+            title: Some("My Movie".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.mkvmerge_args(),
+            "-e track:v1 -e info -s 'title=My Movie'"
+        );
+    }
+
+    #[test]
+    fn encoder_args_with_partial_metadata() {
+        let full = hdr10_metadata();
+        assert_eq!(
+            full.x265_args().unwrap(),
+            "--range limited --colorprim bt2020 --transfer smpte2084 --colormatrix bt2020nc \
+             --max-luma 1000 --min-luma 0 --max-cll 944,143 \
+             --master-display 'G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)'"
+        );
+        assert_eq!(
+            full.rav1e_args().unwrap(),
+            "--range Limited --primaries BT2020 --transfer SMPTE2084 --matrix BT2020NCL \
+             --content-light 944,143 \
+             --mastering-display 'G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)'"
+        );
+
+        let hdr_only = Metadata {
+            basic: None,
+            ..hdr10_metadata()
+        };
+        assert_eq!(
+            hdr_only.x265_args().unwrap(),
+            "--max-luma 1000 --min-luma 0 --max-cll 944,143 \
+             --master-display 'G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)'"
+        );
+        assert_eq!(
+            hdr_only.rav1e_args().unwrap(),
+            "--content-light 944,143 \
+             --mastering-display 'G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)'"
+        );
+
+        let basic_only = Metadata {
+            hdr: None,
+            ..hdr10_metadata()
+        };
+        assert_eq!(
+            basic_only.x265_args().unwrap(),
+            "--range limited --colorprim bt2020 --transfer smpte2084 --colormatrix bt2020nc"
+        );
+        assert_eq!(
+            basic_only.rav1e_args().unwrap(),
+            "--range Limited --primaries BT2020 --transfer SMPTE2084 --matrix BT2020NCL"
+        );
+
+        let neither = Metadata::default();
+        assert_eq!(neither.x265_args().unwrap(), "");
+        assert_eq!(neither.rav1e_args().unwrap(), "");
+    }
+
+    // Whether the colour and HDR metadata are exactly those of `hdr10_metadata`
+    fn is_hdr10_metadata(metadata: &Metadata) -> bool {
+        let expected = hdr10_metadata();
+        metadata.basic == expected.basic && metadata.hdr == expected.hdr
+    }
+
+    #[test]
+    fn merge_basic_only_with_hdr_only() {
+        let basic_only = || Metadata {
+            hdr: None,
+            bit_depth: Some(10),
+            ..hdr10_metadata()
+        };
+        let hdr_only = || Metadata {
+            basic: None,
+            hdr10plus: true,
+            bit_depth: Some(8),
+            ..hdr10_metadata()
+        };
+
+        let merged = basic_only().merge(hdr_only());
+        assert!(is_hdr10_metadata(&merged));
+        assert!(merged.hdr10plus);
+        assert_eq!(merged.bit_depth, Some(10));
+
+        // Either order gives the same colour and HDR metadata, but the first one's bit depth
+        let merged = hdr_only().merge(basic_only());
+        assert!(is_hdr10_metadata(&merged));
+        assert!(merged.hdr10plus);
+        assert_eq!(merged.bit_depth, Some(8));
+    }
+
+    #[test]
+    fn merge_prefers_mastering_display() {
+        let content_light_only = || Metadata {
+            hdr: Some(HdrMetadata {
+                max_content_light: 500,
+                max_frame_light: 100,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let merged = content_light_only().merge(hdr10_metadata());
+        assert!(merged.hdr == hdr10_metadata().hdr);
+
+        let merged = hdr10_metadata().merge(content_light_only());
+        assert!(is_hdr10_metadata(&merged));
+    }
+
+    #[test]
+    fn plan_marks_only_untouched_properties_as_skipped() {
+        let mut metadata = hdr10_metadata();
+        metadata.hdr = None;
+        metadata.basic.as_mut().unwrap().range = Some(ColorRange::Full);
+        metadata.title = Some("Movie".to_string());
+        let plan = metadata.plan(&hdr10_metadata());
+        for line in [
+            "colour-range: 1→2 (change)",
+            "colour-transfer-characteristics: 16 (unchanged)",
+            "title: unset→Movie (set)",
+            "max-content-light: 944 (kept, skip)",
+            "white-coordinates-y: 0.32900 (kept, skip)",
+        ] {
+            assert!(plan.iter().any(|planned| planned == line), "{}", line);
+        }
+        assert_eq!(
+            plan.iter().filter(|line| line.ends_with("skip)")).count(),
+            12
+        );
+    }
+
+    #[test]
+    fn st2086_json_fields() {
+        assert_eq!(
+            hdr10_metadata().st2086_json(),
+            json!({
+                "mastering_display": {
+                    "display_primaries": {
+                        "red": { "x": 0.708, "y": 0.292 },
+                        "green": { "x": 0.170, "y": 0.797 },
+                        "blue": { "x": 0.131, "y": 0.046 },
+                    },
+                    "white_point_chromaticity": { "x": 0.3127, "y": 0.329 },
+                    "max_display_mastering_luminance": 1000.,
+                    "min_display_mastering_luminance": 0.0001,
+                },
+                "content_light_level": { "max_cll": 944, "max_fall": 143 },
+            })
+        );
+        assert_eq!(
+            Metadata::default().st2086_json(),
+            json!({ "mastering_display": null, "content_light_level": null })
+        );
+    }
+
+    #[test]
+    fn tonemap_filter_uses_zscale_names() {
+        assert_eq!(
+            hdr10_metadata().tonemap_filter(),
+            "zscale=tin=smpte2084:pin=2020:min=2020_ncl:rin=limited:t=linear:npl=100,\
+             format=gbrpf32le,tonemap=hable,zscale=t=709:m=709:p=709:r=limited,format=yuv420p"
+        );
+        let mut metadata = hdr10_metadata();
+        let basic = metadata.basic.as_mut().unwrap();
+        basic.transfer = Some(TransferCharacteristics::Bt1361);
+        basic.matrix = Some(MatrixCoefficients::Unspecified);
+        basic.range = None;
+        assert!(metadata
+            .tonemap_filter()
+            .starts_with("zscale=pin=2020:t=linear:"));
+    }
+}
@@ -1,22 +1,167 @@
-use std::{path::Path, process::Command};
+mod av1;
+mod bits;
+mod hevc;
+mod image;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
 
 use anyhow::Result;
 use nom::{
     bytes::complete::tag,
     character::complete::{char, digit1},
-    combinator::map,
-    sequence::{delimited, preceded, separated_pair},
+    combinator::map_res,
+    sequence::{delimited, preceded, separated_pair, tuple},
     IResult,
 };
+use serde::Deserialize;
 
+pub use self::{
+    av1::parse_av1,
+    hevc::parse_hevc,
+    image::{parse_exr, parse_png},
+};
 use crate::{
-    metadata::{BasicMetadata, ColorCoordinates, HdrMetadata, Metadata},
-    values::{
-        parse_color_primaries, parse_color_range, parse_matrix_coefficients,
-        parse_transfer_characteristics,
+    metadata::{
+        chromaticity_from_units, BasicMetadata, ChromaSiting, ColorCoordinates, ColorFormat,
+        DoviMetadata, HdrMetadata, Metadata,
     },
+    tools::{finish_probe, start_probe, tool_command, ToolCommand},
+    values::{ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics},
 };
 
+// HDR side data is sometimes only attached to keyframes after the first,
+// so ffprobe reads every keyframe within this many seconds of the start
+const FFPROBE_SECONDS: u32 = 10;
+
+// ISO base media files start with a box which is usually `ftyp`,
+// but older QuickTime files may start with one of the others
+const MP4_FIRST_BOXES: &[&[u8]] = &[b"ftyp", b"moov", b"mdat", b"wide", b"free"];
+
+// The external tools which we read metadata from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tool {
+    Mkvinfo,
+    Mediainfo,
+    Ffprobe,
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Mkvinfo => "mkvinfo",
+            Tool::Mediainfo => "mediainfo",
+            Tool::Ffprobe => "ffprobe",
+        }
+    }
+
+    // Parses output which was captured from this tool, run with the same arguments as
+    // `command`. ffprobe's output only has the video track it was run on, while the
+    // others show every track, so the one we want is picked out while parsing.
+    pub fn parse(self, output: &str, track: u64) -> Result<Metadata> {
+        match self {
+            Tool::Mkvinfo => parse_mkvinfo(output, track),
+            Tool::Mediainfo => parse_mediainfo(output, track),
+            Tool::Ffprobe => parse_ffprobe(output),
+        }
+    }
+
+    fn command(self, input: &Path, track: u64) -> Command {
+        match self {
+            Tool::Mkvinfo => {
+                let mut cmd = tool_command(self.name());
+                cmd.arg(input);
+                cmd
+            }
+            Tool::Mediainfo => {
+                let mut cmd = tool_command(self.name());
+                cmd.arg(input);
+                cmd
+            }
+            Tool::Ffprobe => {
+                let mut cmd = tool_command(self.name());
+                cmd.arg("-v")
+                    .arg("quiet")
+                    .arg("-print_format")
+                    .arg("json")
+                    .arg("-select_streams")
+                    .arg(format!("v:{}", track - 1))
+                    .arg("-show_frames")
+                    .arg("-show_streams")
+                    .arg("-skip_frame")
+                    .arg("nokey")
+                    .arg("-read_intervals")
+                    .arg(format!("%+{}", FFPROBE_SECONDS))
+                    .arg(input);
+                cmd
+            }
+        }
+    }
+}
+
+// Holds the captured stdout of each tool for each file, so that no tool
+// is run more than once on the same file, however many times it is parsed.
+// Entries for a file must be invalidated once we modify that file.
+pub struct ToolOutput {
+    outputs: Mutex<HashMap<(Tool, PathBuf, u64), Arc<str>>>,
+}
+
+impl ToolOutput {
+    pub fn global() -> &'static ToolOutput {
+        static CACHE: OnceLock<ToolOutput> = OnceLock::new();
+        CACHE.get_or_init(|| ToolOutput {
+            outputs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Returns the output of `tool` for the given video track of `input`,
+    // running it only if it hasn't been run yet
+    pub fn get(&self, tool: Tool, input: &Path, track: u64) -> Result<Arc<str>> {
+        // Only ffprobe is run on a single track, so the others' output can be shared
+        let track = if tool == Tool::Ffprobe { track } else { 1 };
+        let key = (tool, input.to_path_buf(), track);
+        if let Some(output) = self.outputs.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(output));
+        }
+
+        let mut command = tool.command(input, track);
+        start_probe(tool.name());
+        let result = command.tool_output_with_timeout();
+        finish_probe();
+        let result = result?;
+        // Some tools exit with an error but still print something useful,
+        // e.g. mkvinfo when the input isn't a Matroska file
+        if !result.status.success() && result.stdout.iter().all(u8::is_ascii_whitespace) {
+            anyhow::bail!(
+                "{} failed to read {:?}: {}",
+                command.get_program().to_string_lossy(),
+                input,
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+        }
+        let output: Arc<str> = String::from_utf8_lossy(&result.stdout).into();
+        self.outputs
+            .lock()
+            .unwrap()
+            .insert(key, Arc::clone(&output));
+        Ok(output)
+    }
+
+    pub fn invalidate(&self, input: &Path) {
+        self.outputs
+            .lock()
+            .unwrap()
+            .retain(|(_, path, _), _| path != input);
+    }
+}
+
 // MKVInfo may include data that looks like this:
 //
 // |    + Colour matrix coefficients: 9
@@ -38,32 +183,77 @@ use crate::{
 // |     + Minimum luminance: 0.004999999888241291
 //
 // This is the case if the metadata was muxed into the MKV headers.
-pub fn parse_mkvinfo(input: &Path) -> Result<Metadata> {
-    let result = Command::new("mkvinfo").arg(input).output()?;
-    let output = String::from_utf8_lossy(&result.stdout);
-
+pub fn parse_mkvinfo(output: &str, track: u64) -> Result<Metadata> {
     let mut basic = BasicMetadata::default();
     let mut has_basic = false;
     let mut hdr = HdrMetadata::default();
     let mut has_hdr = false;
-    for line in output.lines() {
+    let mut chroma_siting = ChromaSiting::default();
+    let mut format = ColorFormat::default();
+    let mut field_order = None;
+    let mut interlaced = None;
+    let lines = mkvinfo_track_lines(output, track)?;
+    let (title, track_name) = parse_mkvinfo_titles(&lines);
+    for line in lines {
+        if line.contains("Field order:") {
+            field_order = Some(line.split_once(": ").unwrap().1.parse()?);
+            continue;
+        }
+        if line.contains("Interlaced:") {
+            interlaced = Some(line.split_once(": ").unwrap().1.parse::<u8>()?);
+            continue;
+        }
+        if line.contains("Horizontal chroma siting:") {
+            chroma_siting.horizontal = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Vertical chroma siting:") {
+            chroma_siting.vertical = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Colour bits per channel:") {
+            format.bits_per_channel = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Horizontal chroma subsample:") {
+            format.chroma_subsample_horizontal = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Vertical chroma subsample:") {
+            format.chroma_subsample_vertical = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Horizontal Cb subsample:") {
+            format.cb_subsample_horizontal = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
+        if line.contains("Vertical Cb subsample:") {
+            format.cb_subsample_vertical = line.split_once(": ").unwrap().1.parse()?;
+            continue;
+        }
         if line.contains("Colour matrix coefficients:") {
-            basic.matrix = line.split_once(": ").unwrap().1.parse()?;
+            basic.matrix = Some(MatrixCoefficients::from_u8(
+                line.split_once(": ").unwrap().1.parse()?,
+            ));
             has_basic = true;
             continue;
         }
         if line.contains("Colour range:") {
-            basic.range = line.split_once(": ").unwrap().1.parse()?;
-            has_basic = true;
+            basic.range = ColorRange::from_mkvedit_prop(line.split_once(": ").unwrap().1.parse()?);
+            has_basic |= basic.range.is_some();
             continue;
         }
         if line.contains("Colour transfer:") {
-            basic.transfer = line.split_once(": ").unwrap().1.parse()?;
+            basic.transfer = Some(TransferCharacteristics::from_u8(
+                line.split_once(": ").unwrap().1.parse()?,
+            ));
             has_basic = true;
             continue;
         }
         if line.contains("Colour primaries:") {
-            basic.primaries = line.split_once(": ").unwrap().1.parse()?;
+            basic.primaries = Some(ColorPrimaries::from_u8(
+                line.split_once(": ").unwrap().1.parse()?,
+            ));
             has_basic = true;
             continue;
         }
@@ -123,11 +313,11 @@ pub fn parse_mkvinfo(input: &Path) -> Result<Metadata> {
         }
 
         if line.contains("Maximum luminance:") {
-            hdr.max_luma = line.split_once(": ").unwrap().1.parse()?;
+            hdr.max_luma = parse_luminance(line.split_once(": ").unwrap().1)?;
             continue;
         }
         if line.contains("Minimum luminance:") {
-            hdr.min_luma = line.split_once(": ").unwrap().1.parse()?;
+            hdr.min_luma = parse_luminance(line.split_once(": ").unwrap().1)?;
             continue;
         }
     }
@@ -135,9 +325,61 @@ pub fn parse_mkvinfo(input: &Path) -> Result<Metadata> {
     Ok(Metadata {
         basic: if has_basic { Some(basic) } else { None },
         hdr: if has_hdr { Some(hdr) } else { None },
+        chroma_siting: Some(chroma_siting).filter(ChromaSiting::is_specified),
+        color_format: Some(format).filter(ColorFormat::is_specified),
+        // Progressive video usually only has the interlaced flag
+        field_order: field_order.or(interlaced.filter(|&flag| flag == 2).map(|_| 0)),
+        title,
+        track_name,
+        ..Default::default()
     })
 }
 
+// mkvinfo shows the segment's title in the segment information, and each track's
+// name within that track, e.g.
+//
+// |+ Segment information
+// | + Title: Foo
+// |+ Tracks
+// | + Track
+// |  + Track type: video
+// |  + Name: Bar
+//
+// Names are only taken from the first video track, since tags and
+// other tracks have names too.
+fn parse_mkvinfo_titles(lines: &[&str]) -> (Option<String>, Option<String>) {
+    let mut title = None;
+    let mut section = "";
+    // Whether each track is a video track, and its name
+    let mut tracks: Vec<(bool, Option<String>)> = Vec::new();
+    for &line in lines {
+        if let Some(name) = line.strip_prefix("|+ ") {
+            section = name.trim();
+            continue;
+        }
+        if section.starts_with("Segment information") {
+            if let Some(value) = line.strip_prefix("| + Title: ") {
+                title = Some(value.to_string());
+            }
+        } else if section.starts_with("Tracks") {
+            if line.trim_end() == "| + Track" {
+                tracks.push((false, None));
+            } else if let Some(track) = tracks.last_mut() {
+                if let Some(value) = line.strip_prefix("|  + Name: ") {
+                    track.1 = Some(value.to_string());
+                } else if line.starts_with("|  + Track type: video") {
+                    track.0 = true;
+                }
+            }
+        }
+    }
+    let track_name = tracks
+        .into_iter()
+        .find(|&(is_video, _)| is_video)
+        .and_then(|(_, name)| name);
+    (title, track_name)
+}
+
 // MediaInfo may include the following pieces of data:
 //
 // In the x265 headers: master-display=G(13250,34499)B(7499,2999)R(34000,15999)WP(15634,16450)L(10000000,50)cll=944,143
@@ -154,237 +396,849 @@ pub fn parse_mkvinfo(input: &Path) -> Result<Metadata> {
 // Maximum Frame-Average Light Level        : 143 cd/m2
 //
 // We need this if the metadata was encoded into the video stream by x265.
-pub fn parse_mediainfo(input: &Path) -> Result<Metadata> {
-    let result = Command::new("mediainfo").arg(input).output()?;
-    let output = String::from_utf8_lossy(&result.stdout);
-
+//
+// MediaInfo will also tell us if the stream has dynamic HDR metadata:
+//
+// HDR format                               : Dolby Vision, Version 1.0, dvhe.08.06, BL+RPU, HDR10 compatible / SMPTE ST 2086, HDR10 compatible
+// HDR format                               : SMPTE ST 2094 App 4, Version 1, HDR10+ Profile B compatible
+pub fn parse_mediainfo(output: &str, track: u64) -> Result<Metadata> {
     let mut basic = BasicMetadata::default();
     let mut has_basic = false;
     let mut hdr = HdrMetadata::default();
     let mut has_hdr = false;
+    let mut dolby_vision = None;
+    let mut hdr10plus = false;
+    let mut bit_depth = None;
+    let mut chroma_subsampling = None;
+    let mut scan_type = None;
+    let mut scan_order = None;
+    // Audio tracks have a bit depth too, so only read it from the selected video track
+    let mut section = "";
     for line in output.lines() {
-        if line.contains("Matrix coefficients") {
-            basic.matrix = parse_matrix_coefficients(line.split_once(": ").unwrap().1);
-            has_basic = true;
-            continue;
-        }
-        if line.contains("Color range") {
-            basic.range = parse_color_range(line.split_once(": ").unwrap().1);
-            has_basic = true;
-            continue;
-        }
-        if line.contains("Transfer characteristics") {
-            basic.transfer = parse_transfer_characteristics(line.split_once(": ").unwrap().1);
-            has_basic = true;
-            continue;
-        }
-        if line.contains("Color primaries") {
-            basic.primaries = parse_color_primaries(line.split_once(": ").unwrap().1);
-            has_basic = true;
-            continue;
-        }
-
-        // HDR details
-        if line.contains("Mastering display color primaries") {
-            has_hdr = true;
-            continue;
-        }
-        if line.contains("Maximum Content Light Level") {
-            hdr.max_content_light = line
-                .split_once(": ")
-                .unwrap()
-                .1
-                .trim_end_matches(" cd/m2")
-                .parse()?;
-            continue;
-        }
-        if line.contains("Maximum Frame-Average Light Level") {
-            hdr.max_frame_light = line
-                .split_once(": ")
-                .unwrap()
-                .1
-                .trim_end_matches(" cd/m2")
-                .parse()?;
-            continue;
+        if !line.is_empty() && !line.contains(':') {
+            section = line.trim();
         }
-        if line.contains("Mastering display luminance") {
-            let output = line.split_once(": ").unwrap().1;
-            let (min, max) = output.split_once(", ").unwrap();
-            hdr.min_luma = min
-                .trim_start_matches("min: ")
-                .trim_end_matches(" cd/m2")
-                .parse()?;
-            hdr.max_luma = max
-                .trim_start_matches("max: ")
-                .trim_end_matches(" cd/m2")
-                .parse()?;
+        if section.starts_with("Video") && !is_selected_video(section, track) {
             continue;
         }
+        // Field names are padded to line up the values, and the amount of padding
+        // varies between versions, so only the first colon can be relied on
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        // Match the whole name, so that e.g. `Mastering display color primaries`
+        // or `Color primaries_Original` aren't mistaken for `Color primaries`
+        match key {
+            "HDR format" => {
+                if value.contains("Dolby Vision") {
+                    dolby_vision = Some(parse_mediainfo_dovi(value));
+                }
+                hdr10plus |= value.contains("SMPTE ST 2094") || value.contains("HDR10+");
+            }
+            "Matrix coefficients" => {
+                basic.matrix = Some(MatrixCoefficients::from_mediainfo(value)?);
+                has_basic = true;
+            }
+            "Color range" => {
+                basic.range = Some(ColorRange::from_mediainfo(value)?);
+                has_basic = true;
+            }
+            "Transfer characteristics" => {
+                basic.transfer = Some(TransferCharacteristics::from_mediainfo(value)?);
+                has_basic = true;
+            }
+            "Color primaries" => {
+                basic.primaries = Some(ColorPrimaries::from_mediainfo(value)?);
+                has_basic = true;
+            }
+            "Bit depth" if is_selected_video(section, track) && bit_depth.is_none() => {
+                bit_depth = Some(parse_localized(value.trim_end_matches(" bits"))?);
+            }
+            "Scan type" if is_selected_video(section, track) => scan_type = Some(value),
+            "Scan order" if is_selected_video(section, track) => scan_order = Some(value),
+            "Chroma subsampling"
+                if is_selected_video(section, track) && chroma_subsampling.is_none() =>
+            {
+                // Some formats add a note after it, e.g. `4:2:0 (Type 2)`
+                chroma_subsampling = value.split_whitespace().next().map(String::from);
+            }
 
-        if line.contains("Encoding settings") && line.contains("master-display") {
-            let settings = line.split_once(": ").unwrap().1;
-            hdr.color_coords = Some(parse_x265_settings(settings)?);
+            // HDR details
+            "Mastering display color primaries" => {
+                has_hdr = true;
+            }
+            "Maximum Content Light Level" => {
+                hdr.max_content_light = parse_luminance(value)?.round() as u32;
+            }
+            "Maximum Frame-Average Light Level" => {
+                hdr.max_frame_light = parse_luminance(value)?.round() as u32;
+            }
+            "Mastering display luminance" => {
+                // Localized numbers never have a space after the decimal comma,
+                // e.g. `min: 0,0050 cd/m2, max: 1000 cd/m2`
+                let (min, max) = value.split_once(", ").ok_or_else(|| {
+                    anyhow::anyhow!("Unrecognized mastering display luminance: {}", value)
+                })?;
+                hdr.min_luma = parse_luminance(min.trim().trim_start_matches("min:"))?;
+                hdr.max_luma = parse_luminance(max.trim().trim_start_matches("max:"))?;
+            }
+            "Encoding settings" if value.contains("master-display") => {
+                let settings = parse_x265_settings(value)?;
+                hdr.color_coords = settings.color_coords;
+                // mediainfo's own HDR fields take precedence, wherever they appear
+                if hdr.max_luma == 0. {
+                    hdr.max_luma = settings.max_luma;
+                    hdr.min_luma = settings.min_luma;
+                }
+                if hdr.max_content_light == 0 && hdr.max_frame_light == 0 {
+                    hdr.max_content_light = settings.max_content_light;
+                    hdr.max_frame_light = settings.max_frame_light;
+                }
+                has_hdr = true;
+            }
+            _ => (),
         }
     }
 
     Ok(Metadata {
         basic: if has_basic { Some(basic) } else { None },
         hdr: if has_hdr { Some(hdr) } else { None },
+        dolby_vision,
+        hdr10plus,
+        bit_depth,
+        chroma_subsampling,
+        chroma_siting: None,
+        // mediainfo doesn't show the container's values, only the stream's
+        color_format: None,
+        field_order: scan_type.map(|scan_type| match (scan_type, scan_order) {
+            ("Progressive", _) => 0,
+            (_, Some("Top Field First")) => 1,
+            (_, Some("Bottom Field First")) => 6,
+            _ => 2,
+        }),
+        title: None,
+        track_name: None,
     })
 }
 
-// Takes in a string that contains a substring in the format:
-// master-display=G(13250,34499)B(7499,2999)R(34000,15999)WP(15634,16450)L(10000000,50)cll=944,143
+// mediainfo calls the section `Video`, or `Video #1`, `Video #2` and so on if there are several
+fn is_selected_video(section: &str, track: u64) -> bool {
+    (track == 1 && section == "Video") || section == format!("Video #{}", track)
+}
+
+// mediainfo formats numbers according to the system locale,
+// so decimals may use a comma instead of a period
+fn parse_localized<T: FromStr>(value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok(value.trim().replace(',', ".").parse()?)
+}
+
+// The colour lines don't say which track they belong to, so this leaves out the lines
+// of every track except the given video track, counting from 1. Output without any
+// tracks, e.g. only part of it, is kept as it is.
+fn mkvinfo_track_lines(output: &str, track: u64) -> Result<Vec<&str>> {
+    // Each track's lines, or `None` for lines outside of any track
+    let mut blocks: Vec<(Option<usize>, &str)> = Vec::new();
+    let mut video_tracks = Vec::new();
+    let mut current = None;
+    let mut count = 0;
+    for line in output.lines() {
+        if line.starts_with("|+ ") {
+            current = None;
+        } else if line.trim_end() == "| + Track" {
+            current = Some(count);
+            count += 1;
+        } else if current.is_some() && line.starts_with("|  + Track type: video") {
+            video_tracks.push(current);
+        }
+        blocks.push((current, line));
+    }
+    if count == 0 {
+        return Ok(output.lines().collect());
+    }
+    let selected = video_tracks
+        .get(track as usize - 1)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("Input has no video track {}", track))?;
+    Ok(blocks
+        .into_iter()
+        .filter(|&(block, _)| block.is_none() || block == selected)
+        .map(|(_, line)| line)
+        .collect())
+}
+
+// Luminances may be followed by their unit, with or without a space, and may be
+// in scientific notation, e.g. `1000.0000 cd/m2` or `5e-3 cd/m2`
+fn parse_luminance(value: &str) -> Result<f64> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && !".,eE+-".contains(c))
+        .unwrap_or(value.len());
+    parse_localized(&value[..end])
+}
+
+// mediainfo describes Dolby Vision like so:
+// HDR format : Dolby Vision, Version 1.0, dvhe.08.06, BL+RPU, HDR10 compatible / SMPTE ST 2086, HDR10 compatible
 //
-// Also using unwrap here because I don't want to fight the borrow checker anymore.
-fn parse_x265_settings(input: &str) -> Result<ColorCoordinates> {
+// where `dvhe.08.06` is the codec string, containing the profile and level.
+fn parse_mediainfo_dovi(format: &str) -> DoviMetadata {
+    let mut dovi = DoviMetadata {
+        rpu_present: format.contains("RPU"),
+        ..Default::default()
+    };
+    let codec = format
+        .split([',', '/'])
+        .map(str::trim)
+        .find(|part| part.starts_with("dv") || part.starts_with("dav1"));
+    if let Some(codec) = codec {
+        let mut parts = codec.split('.').skip(1);
+        dovi.profile = parts.next().and_then(|profile| profile.parse().ok());
+        dovi.level = parts.next().and_then(|level| level.parse().ok());
+    }
+    dovi
+}
+
+// Takes in a string that contains substrings in the format:
+// master-display=G(13250,34499)B(7499,2999)R(34000,15999)WP(15634,16450)L(10000000,50) / cll=944,143
+//
+// The settings are separated by ` / `, and any others around these are ignored.
+// Within the master display, the parts may be in any order and contain whitespace,
+// since not every version of x265 writes them the same way. The luminances are left
+// as zero if `L(...)` is absent, and the content light levels if `cll=` is absent.
+fn parse_x265_settings(input: &str) -> Result<HdrMetadata> {
     const MASTER_DISPLAY_HEADER: &str = "master-display=";
+    const CLL_HEADER: &str = "cll=";
     let header_pos = input
         .find(MASTER_DISPLAY_HEADER)
         .ok_or_else(|| anyhow::anyhow!("Failed to find master display header"))?;
-    let input = &input[(header_pos + MASTER_DISPLAY_HEADER.len())..];
-    let (input, (gx, gy)) = preceded(char('G'), get_coordinate_pair)(input).unwrap();
-    let (input, (bx, by)) = preceded(char('B'), get_coordinate_pair)(input).unwrap();
-    let (input, (rx, ry)) = preceded(char('R'), get_coordinate_pair)(input).unwrap();
-    let (_, (wx, wy)) = preceded(tag("WP"), get_coordinate_pair)(input).unwrap();
+    let master_display: String = input[(header_pos + MASTER_DISPLAY_HEADER.len())..]
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let coordinate_pair = |name: &str| -> Result<Option<(u32, u32)>> {
+        let Some(pos) = master_display.find(&format!("{}(", name)) else {
+            return Ok(None);
+        };
+        let (_, pair) =
+            get_coordinate_pair(&master_display[(pos + name.len())..]).map_err(|_| {
+                anyhow::anyhow!(
+                    "Invalid master display in encoding settings: {}",
+                    master_display
+                )
+            })?;
+        Ok(Some(pair))
+    };
+    let required_pair = |name: &str| {
+        coordinate_pair(name)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Master display in encoding settings has no {} coordinates",
+                name
+            )
+        })
+    };
+    let (gx, gy) = required_pair("G")?;
+    let (bx, by) = required_pair("B")?;
+    let (rx, ry) = required_pair("R")?;
+    let (wx, wy) = required_pair("WP")?;
+    let (max_luma, min_luma) = coordinate_pair("L")?.unwrap_or_default();
+
+    let (max_content_light, max_frame_light) = match input.find(CLL_HEADER) {
+        Some(pos) => {
+            let cll = &input[(pos + CLL_HEADER.len())..];
+            let (_, (max_cll, max_fall)) =
+                separated_pair(digit1, char(','), digit1)(cll).map_err(|_: nom::Err<()>| {
+                    anyhow::anyhow!("Invalid content light levels in encoding settings")
+                })?;
+            (max_cll.parse()?, max_fall.parse()?)
+        }
+        None => (0, 0),
+    };
 
     // Why 50000? Why indeed.
-    Ok(ColorCoordinates {
-        red: (rx as f64 / 50000., ry as f64 / 50000.),
-        green: (gx as f64 / 50000., gy as f64 / 50000.),
-        blue: (bx as f64 / 50000., by as f64 / 50000.),
-        white: (wx as f64 / 50000., wy as f64 / 50000.),
+    Ok(HdrMetadata {
+        color_coords: Some(ColorCoordinates {
+            red: (chromaticity_from_units(rx), chromaticity_from_units(ry)),
+            green: (chromaticity_from_units(gx), chromaticity_from_units(gy)),
+            blue: (chromaticity_from_units(bx), chromaticity_from_units(by)),
+            white: (chromaticity_from_units(wx), chromaticity_from_units(wy)),
+        }),
+        max_luma: max_luma as f64 / 10000.,
+        min_luma: min_luma as f64 / 10000.,
+        max_content_light,
+        max_frame_light,
+    })
+}
+
+// Parses a master display given by the user, in the format used by x265:
+// G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)
+//
+// Luminances are in units of 0.0001 cd/m².
+pub fn parse_master_display(input: &str) -> Result<HdrMetadata> {
+    let (rest, ((gx, gy), (bx, by), (rx, ry), (wx, wy), (max, min))) = tuple((
+        preceded(char('G'), get_coordinate_pair),
+        preceded(char('B'), get_coordinate_pair),
+        preceded(char('R'), get_coordinate_pair),
+        preceded(tag("WP"), get_coordinate_pair),
+        preceded(char('L'), get_coordinate_pair),
+    ))(input.trim())
+    .map_err(|_| anyhow::anyhow!("Invalid master display: {}", input))?;
+    if !rest.is_empty() {
+        anyhow::bail!("Invalid master display: {}", input);
+    }
+
+    Ok(HdrMetadata {
+        color_coords: Some(ColorCoordinates {
+            red: (chromaticity_from_units(rx), chromaticity_from_units(ry)),
+            green: (chromaticity_from_units(gx), chromaticity_from_units(gy)),
+            blue: (chromaticity_from_units(bx), chromaticity_from_units(by)),
+            white: (chromaticity_from_units(wx), chromaticity_from_units(wy)),
+        }),
+        max_luma: max as f64 / 10000.,
+        min_luma: min as f64 / 10000.,
+        ..Default::default()
     })
 }
 
 fn get_coordinate_pair(input: &str) -> IResult<&str, (u32, u32)> {
-    map(
+    map_res(
         delimited(
             char('('),
             separated_pair(digit1, char(','), digit1),
             char(')'),
         ),
-        |(x, y): (&str, &str)| (x.parse::<u32>().unwrap(), y.parse::<u32>().unwrap()),
+        |(x, y): (&str, &str)| -> Result<_, std::num::ParseIntError> {
+            Ok((x.parse::<u32>()?, y.parse::<u32>()?))
+        },
     )(input)
 }
 
-// And then there are some videos where the data only shows in ffprobe.
-//
-// Like so:
-//
-// [SIDE_DATA]
-// side_data_type=Mastering display metadata
-// red_x=34000/50000
-// red_y=15999/50000
-// green_x=13250/50000
-// green_y=34499/50000
-// blue_x=7499/50000
-// blue_y=2999/50000
-// white_point_x=15634/50000
-// white_point_y=16450/50000
-// min_luminance=50/10000
-// max_luminance=10000000/10000
-// [/SIDE_DATA]
-// [SIDE_DATA]
-// side_data_type=Content light level metadata
-// max_content=944
-// max_average=143
-// [/SIDE_DATA]
-//
-// This only looks at HDR data, because at least one of mediainfo
-// or mkvinfo should have found the color primary data.
-// Or your source is badly broken.
-pub fn parse_ffprobe(input: &Path) -> Result<Option<HdrMetadata>> {
-    let result = Command::new("ffprobe")
-        .arg("-v")
-        .arg("quiet")
-        .arg("-select_streams")
-        .arg("v:0")
-        .arg("-show_frames")
-        .arg("-read_intervals")
-        .arg("%+#1")
-        .arg(input)
-        .output()?;
-    let output = String::from_utf8_lossy(&result.stdout);
-
-    if !(output.contains("side_data_type=Mastering display metadata")
-        && output.contains("side_data_type=Content light level metadata"))
-    {
-        return Ok(None);
+// The parts of `ffprobe -print_format json` output which we care about.
+// Any other fields are ignored.
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    frames: Vec<FfprobeFrame>,
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+}
+
+// Color values use ffmpeg's names, e.g. `bt2020nc` or `smpte2084`,
+// and are omitted entirely if the stream doesn't specify them.
+#[derive(Deserialize)]
+struct FfprobeStream {
+    pix_fmt: Option<String>,
+    chroma_location: Option<String>,
+    field_order: Option<String>,
+    color_range: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<FfprobeStreamSideData>,
+}
+
+// The only stream side data we use is the `DOVI configuration record`
+#[derive(Deserialize)]
+struct FfprobeStreamSideData {
+    side_data_type: String,
+    dv_profile: Option<u8>,
+    dv_level: Option<u8>,
+    rpu_present_flag: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFrame {
+    #[serde(default)]
+    side_data_list: Vec<FfprobeSideData>,
+}
+
+// Each side data entry only has the fields relevant to its `side_data_type`.
+// Chromaticities and luminances are given as fractions, e.g. `34000/50000`.
+#[derive(Deserialize)]
+struct FfprobeSideData {
+    side_data_type: String,
+    red_x: Option<String>,
+    red_y: Option<String>,
+    green_x: Option<String>,
+    green_y: Option<String>,
+    blue_x: Option<String>,
+    blue_y: Option<String>,
+    white_point_x: Option<String>,
+    white_point_y: Option<String>,
+    min_luminance: Option<String>,
+    max_luminance: Option<String>,
+    max_content: Option<u32>,
+    max_average: Option<u32>,
+}
+
+// Whether the input is an MP4 or MOV file, which keep their colour metadata in the
+// `colr` box instead of Matroska's Colour element, so mkvinfo can't read it
+pub(crate) fn is_mp4(input: &Path) -> Result<bool> {
+    let mut header = [0; 8];
+    let mut file = File::open(input)?;
+    if file.read_exact(&mut header).is_err() {
+        return Ok(false);
     }
+    Ok(MP4_FIRST_BOXES.contains(&&header[4..8]))
+}
 
-    let mut hdr = HdrMetadata::default();
-    for line in output.lines() {
-        if line.starts_with("red_x=") {
-            // This should always be the first piece of color data, so we initialize here
-            hdr.color_coords = Some(ColorCoordinates::default());
+// Some videos only have their HDR data in the frame side data which ffprobe shows,
+// i.e. the `Mastering display metadata` and `Content light level metadata` entries,
+// whose values are fractions such as `"red_x": "34000/50000"`. The first few frames
+// are checked, since files concatenated from different masters may change partway through.
+// The Dolby Vision and HDR10+ side data entries only mark that dynamic metadata is present.
+pub fn parse_ffprobe(output: &str) -> Result<Metadata> {
+    let output: FfprobeOutput = serde_json::from_str(output)?;
 
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().red.0 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
-        }
-        if line.starts_with("red_y=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().red.1 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
-        }
-        if line.starts_with("green_x=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().green.0 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
-        }
-        if line.starts_with("green_y=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().green.1 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+    let mut found: Option<HdrMetadata> = None;
+    // Whether `found` came from a frame with both mastering display and content light side data
+    let mut found_complete = false;
+    let mut mismatches = Vec::new();
+    let mut dolby_vision = output
+        .streams
+        .first()
+        .and_then(|stream| {
+            stream
+                .side_data_list
+                .iter()
+                .find(|side_data| side_data.side_data_type == "DOVI configuration record")
+        })
+        .map(|record| DoviMetadata {
+            profile: record.dv_profile,
+            level: record.dv_level,
+            rpu_present: record.rpu_present_flag == Some(1),
+        });
+    let mut hdr10plus = false;
+    for frame in &output.frames {
+        for side_data in &frame.side_data_list {
+            // Older ffmpeg versions only expose the RPU on each frame, not the configuration record
+            if side_data.side_data_type.starts_with("Dolby Vision") {
+                dolby_vision
+                    .get_or_insert_with(Default::default)
+                    .rpu_present = true;
+            }
+            hdr10plus |= side_data
+                .side_data_type
+                .starts_with("HDR Dynamic Metadata SMPTE2094-40");
         }
-        if line.starts_with("blue_x=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().blue.0 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+
+        let hdr = match parse_ffprobe_frame(frame)? {
+            Some(hdr) => hdr,
+            None => continue,
+        };
+        let complete = ["Mastering display metadata", "Content light level metadata"]
+            .iter()
+            .all(|kind| {
+                frame
+                    .side_data_list
+                    .iter()
+                    .any(|side_data| side_data.side_data_type == *kind)
+            });
+        match found {
+            None => {
+                found = Some(hdr);
+                found_complete = complete;
+            }
+            Some(ref first) => {
+                if first.color_coords.is_some()
+                    && hdr.color_coords.is_some()
+                    && !first.same_mastering_display(&hdr)
+                    && !mismatches
+                        .iter()
+                        .any(|other: &HdrMetadata| other.same_mastering_display(&hdr))
+                {
+                    mismatches.push(hdr);
+                } else if complete && !found_complete {
+                    // Prefer the first frame which has all of the HDR side data
+                    found = Some(hdr);
+                    found_complete = true;
+                }
+            }
         }
-        if line.starts_with("blue_y=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().blue.1 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+    }
+
+    if let Some(ref first) = found {
+        if !mismatches.is_empty() {
+            let values: Vec<_> = std::iter::once(first)
+                .chain(mismatches.iter())
+                .map(|hdr| format!("    {}", hdr.describe_mastering_display()))
+                .collect();
+            log::warn!(
+                "File has inconsistent mastering display metadata across frames:\n{}",
+                values.join("\n")
+            );
         }
-        if line.starts_with("white_point_x=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().white.0 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+    }
+    let basic = match output.streams.first() {
+        Some(stream) => parse_ffprobe_stream(stream)?,
+        None => None,
+    };
+    let (bit_depth, chroma_subsampling) = output
+        .streams
+        .first()
+        .and_then(|stream| stream.pix_fmt.as_deref())
+        .map_or((None, None), parse_pix_fmt);
+    let chroma_siting = output
+        .streams
+        .first()
+        .and_then(|stream| stream.chroma_location.as_deref())
+        .and_then(parse_chroma_location);
+    let field_order = output
+        .streams
+        .first()
+        .and_then(|stream| stream.field_order.as_deref())
+        .and_then(parse_ffprobe_field_order);
+    Ok(Metadata {
+        basic,
+        hdr: found,
+        dolby_vision,
+        hdr10plus,
+        bit_depth,
+        chroma_subsampling,
+        chroma_siting,
+        color_format: None,
+        field_order,
+        title: None,
+        track_name: None,
+    })
+}
+
+// ffprobe gives the order the fields are coded in, then the order they're displayed in
+fn parse_ffprobe_field_order(field_order: &str) -> Option<u8> {
+    match field_order {
+        "progressive" => Some(0),
+        "tt" => Some(1),
+        "bb" => Some(6),
+        "tb" => Some(9),
+        "bt" => Some(14),
+        _ => None,
+    }
+}
+
+// Matroska can't describe siting below the luma samples, i.e. `bottomleft` and `bottom`
+fn parse_chroma_location(location: &str) -> Option<ChromaSiting> {
+    let (horizontal, vertical) = match location {
+        "left" => (1, 2),
+        "center" => (2, 2),
+        "topleft" => (1, 1),
+        "top" => (2, 1),
+        _ => return None,
+    };
+    Some(ChromaSiting {
+        horizontal,
+        vertical,
+    })
+}
+
+// Reads the bit depth and chroma subsampling from an ffmpeg pixel format, e.g.
+// `yuv420p10le` is 10 bit 4:2:0. Formats without a bit depth, like `yuv420p`, are 8 bit.
+fn parse_pix_fmt(pix_fmt: &str) -> (Option<u8>, Option<String>) {
+    let chroma_subsampling = if pix_fmt.starts_with("gray") {
+        Some("4:0:0")
+    } else if pix_fmt.starts_with("gbr") || pix_fmt.contains("444") {
+        Some("4:4:4")
+    } else if pix_fmt.contains("422") {
+        Some("4:2:2")
+    } else if pix_fmt.contains("420") || pix_fmt.starts_with("nv") || pix_fmt.starts_with("p0") {
+        Some("4:2:0")
+    } else {
+        None
+    };
+    if chroma_subsampling.is_none() {
+        // Not a format we know how to read, e.g. RGB
+        return (None, None);
+    }
+
+    // The bit depth is the digits at the end, after the endianness is removed.
+    // The `12` in `nv12` is not a bit depth.
+    let name = pix_fmt.trim_end_matches("le").trim_end_matches("be");
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let bit_depth = if pix_fmt.starts_with("nv") || digits == 0 {
+        8
+    } else {
+        match name[name.len() - digits..].parse() {
+            Ok(bit_depth) => bit_depth,
+            Err(_) => return (None, chroma_subsampling.map(String::from)),
         }
-        if line.starts_with("white_point_y=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.color_coords.as_mut().unwrap().white.1 =
-                num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+    };
+    (Some(bit_depth), chroma_subsampling.map(String::from))
+}
+
+// Parses the color properties of the video stream from ffprobe's output
+fn parse_ffprobe_stream(stream: &FfprobeStream) -> Result<Option<BasicMetadata>> {
+    let mut basic = BasicMetadata::default();
+    let mut has_basic = false;
+    if let Some(ref matrix) = stream.color_space {
+        basic.matrix = Some(MatrixCoefficients::from_ffmpeg(matrix)?);
+        has_basic = true;
+    }
+    // ffprobe reports `unknown` here when the range isn't set,
+    // which has no equivalent in our values
+    if let Some(range) = stream
+        .color_range
+        .as_ref()
+        .filter(|range| *range != "unknown")
+    {
+        basic.range = Some(ColorRange::from_ffmpeg(range)?);
+        has_basic = true;
+    }
+    if let Some(ref transfer) = stream.color_transfer {
+        basic.transfer = Some(TransferCharacteristics::from_ffmpeg(transfer)?);
+        has_basic = true;
+    }
+    if let Some(ref primaries) = stream.color_primaries {
+        basic.primaries = Some(ColorPrimaries::from_ffmpeg(primaries)?);
+        has_basic = true;
+    }
+    Ok(if has_basic { Some(basic) } else { None })
+}
+
+// Parses the side data from a single frame of ffprobe's output
+fn parse_ffprobe_frame(frame: &FfprobeFrame) -> Result<Option<HdrMetadata>> {
+    // Either of these may be present without the other,
+    // e.g. content light levels without mastering display data.
+    let mastering_display = frame
+        .side_data_list
+        .iter()
+        .find(|side_data| side_data.side_data_type == "Mastering display metadata");
+    let content_light = frame
+        .side_data_list
+        .iter()
+        .find(|side_data| side_data.side_data_type == "Content light level metadata");
+    if mastering_display.is_none() && content_light.is_none() {
+        return Ok(None);
+    }
+
+    let mut hdr = HdrMetadata::default();
+    if let Some(side_data) = mastering_display {
+        if side_data.red_x.is_some() {
+            hdr.color_coords = Some(ColorCoordinates {
+                red: (
+                    parse_fraction(&side_data.red_x)?,
+                    parse_fraction(&side_data.red_y)?,
+                ),
+                green: (
+                    parse_fraction(&side_data.green_x)?,
+                    parse_fraction(&side_data.green_y)?,
+                ),
+                blue: (
+                    parse_fraction(&side_data.blue_x)?,
+                    parse_fraction(&side_data.blue_y)?,
+                ),
+                white: (
+                    parse_fraction(&side_data.white_point_x)?,
+                    parse_fraction(&side_data.white_point_y)?,
+                ),
+            });
         }
-        if line.starts_with("min_luminance=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.min_luma = num.parse::<f64>()? / denom.parse::<f64>()?;
-            continue;
+        if side_data.min_luminance.is_some() {
+            hdr.min_luma = parse_fraction(&side_data.min_luminance)?;
         }
-        if line.starts_with("max_luminance=") {
-            let (num, denom) = line.split_once('=').unwrap().1.split_once('/').unwrap();
-            hdr.max_luma = num.parse::<u32>()? / denom.parse::<u32>()?;
-            continue;
+        if side_data.max_luminance.is_some() {
+            hdr.max_luma = parse_fraction(&side_data.max_luminance)?;
         }
+    }
+    if let Some(side_data) = content_light {
+        hdr.max_content_light = side_data.max_content.unwrap_or_default();
+        hdr.max_frame_light = side_data.max_average.unwrap_or_default();
+    }
+    Ok(Some(hdr))
+}
 
-        if line.starts_with("max_content=") {
-            hdr.max_content_light = line.split_once('=').unwrap().1.parse()?;
-            continue;
-        }
-        if line.starts_with("max_average=") {
-            hdr.max_frame_light = line.split_once('=').unwrap().1.parse()?;
-            continue;
+// Parses a value such as `34000/50000` into a float.
+// Plain numbers without a denominator are also accepted.
+fn parse_fraction(value: &Option<String>) -> Result<f64> {
+    let value = value
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Missing value in ffprobe side data"))?;
+    match value.split_once('/') {
+        Some((num, denom)) => Ok(num.trim().parse::<f64>()? / denom.trim().parse::<f64>()?),
+        None => Ok(value.trim().parse()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mkvinfo_unset_range_is_not_read_as_full() {
+        let output = "|+ Tracks\n\
+                      | + Track\n\
+                      |  + Track type: video\n\
+                      |  + Video track\n\
+                      |   + Colour matrix coefficients: 9\n\
+                      |   + Colour range: 0\n";
+        let basic = parse_mkvinfo(output, 1).unwrap().basic.unwrap();
+        assert_eq!(basic.range, None);
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+
+        let output = output.replace("Colour range: 0", "Colour range: 1");
+        let basic = parse_mkvinfo(&output, 1).unwrap().basic.unwrap();
+        assert_eq!(basic.range, Some(ColorRange::Limited));
+    }
+
+    fn ffprobe_mastering_display(max_luminance: &str, min_luminance: &str) -> String {
+        format!(
+            r#"{{
+                "frames": [{{
+                    "side_data_list": [{{
+                        "side_data_type": "Mastering display metadata",
+                        "red_x": "34000/50000",
+                        "red_y": "16000/50000",
+                        "green_x": "13250/50000",
+                        "green_y": "34500/50000",
+                        "blue_x": "7500/50000",
+                        "blue_y": "3000/50000",
+                        "white_point_x": "15635/50000",
+                        "white_point_y": "16450/50000",
+                        "min_luminance": "{}",
+                        "max_luminance": "{}"
+                    }}]
+                }}],
+                "streams": []
+            }}"#,
+            min_luminance, max_luminance
+        )
+    }
+
+    #[test]
+    fn ffprobe_fractional_max_luminance() {
+        let metadata =
+            parse_ffprobe(&ffprobe_mastering_display("10000000/10000", "50/10000")).unwrap();
+        let hdr = metadata.hdr.unwrap();
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.min_luma, 0.005);
+
+        // 999.99 must not be truncated to 999
+        let metadata =
+            parse_ffprobe(&ffprobe_mastering_display("9999900/10000", "1/10000")).unwrap();
+        let hdr = metadata.hdr.unwrap();
+        assert_eq!(hdr.max_luma, 999.99);
+        assert_eq!(hdr.min_luma, 0.0001);
+        assert!(hdr.describe_mastering_display().ends_with("L(9999900,1)"));
+    }
+
+    #[test]
+    fn mediainfo_localized_luminance() {
+        let output = "Video\n\
+                      Mastering display color primaries        : BT.2020\n\
+                      Mastering display luminance              : min: 0,0050 cd/m2, max: 1000 cd/m2\n\
+                      Maximum Content Light Level              : 1000,4 cd/m2\n\
+                      Maximum Frame-Average Light Level        : 400,5 cd/m2\n";
+        let hdr = parse_mediainfo(output, 1).unwrap().hdr.unwrap();
+        assert_eq!(hdr.min_luma, 0.005);
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.max_content_light, 1000);
+        assert_eq!(hdr.max_frame_light, 401);
+
+        let output = output.replace("max: 1000 cd/m2", "max: 1000,5 cd/m2");
+        let hdr = parse_mediainfo(&output, 1).unwrap().hdr.unwrap();
+        assert_eq!(hdr.min_luma, 0.005);
+        assert_eq!(hdr.max_luma, 1000.5);
+    }
+
+    #[test]
+    fn mediainfo_padding() {
+        for output in [
+            "Video\nTransfer characteristics                 : PQ\nColor primaries                          : BT.2020\n",
+            "Video\nTransfer characteristics: PQ\nColor primaries: BT.2020\n",
+            "Video\nTransfer characteristics\t: PQ\nColor primaries\t\t: BT.2020\n",
+        ] {
+            let basic = parse_mediainfo(output, 1).unwrap().basic.unwrap();
+            assert_eq!(basic.transfer, Some(TransferCharacteristics::Pq), "{:?}", output);
+            assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020), "{:?}", output);
         }
+
+        // Only whole field names match, so these aren't read as the colour primaries
+        let output = "Video\n\
+                      Color primaries_Original                 : BT.709\n\
+                      Color primaries_Source                   : 2 frames\n\
+                      Mastering display color primaries        : Display P3\n";
+        assert!(parse_mediainfo(output, 1).unwrap().basic.is_none());
+    }
+
+    #[test]
+    fn x265_encoding_settings() {
+        // x265 3.5
+        let settings = "cpuid=1111039 / frame-threads=4 / numa-pools=16 / wpp / no-pmode / \
+                        no-pmode / no-psnr / no-ssim / log-level=2 / input-csp=1 / \
+                        input-res=3840x2160 / interlace=0 / total-frames=0 / level-idc=51 / \
+                        high-tier=1 / uhd-bd=0 / ref=4 / no-allow-non-conformance / \
+                        repeat-headers / annexb / aud / hrd / info / hash=0 / \
+                        master-display=G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1) / \
+                        cll=1000,400 / min-luma=0 / max-luma=1023 / log2-max-poc-lsb=8 / \
+                        vui-timing-info / vui-hrd-info / slices=1 / no-opt-qp-pps / hdr10 / \
+                        hdr10-opt / no-dhdr10-opt / no-idr-recovery-sei / analysis-reuse-level=0";
+        let hdr = parse_x265_settings(settings).unwrap();
+        let coords = hdr.color_coords.unwrap();
+        assert_eq!(coords.green, (0.265, 0.69));
+        assert_eq!(coords.blue, (0.15, 0.06));
+        assert_eq!(coords.red, (0.68, 0.32));
+        assert_eq!(coords.white, (0.3127, 0.329));
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.min_luma, 0.0001);
+        assert_eq!(hdr.max_content_light, 1000);
+        assert_eq!(hdr.max_frame_light, 400);
+
+        // x265 2.x, with spaces inside the master display and no content light levels
+        let settings = "wpp / ctu=64 / min-cu-size=8 / max-tu-size=32 / \
+                        master-display=G(8500,39850) B(6550,2300) R(35400,14600) WP(15635,16450) L(40000000,50) / \
+                        min-luma=0 / max-luma=1023 / log2-max-poc-lsb=8";
+        let hdr = parse_x265_settings(settings).unwrap();
+        assert_eq!(hdr.color_coords.unwrap().red, (0.708, 0.292));
+        assert_eq!(hdr.max_luma, 4000.);
+        assert_eq!(hdr.min_luma, 0.005);
+        assert_eq!(hdr.max_content_light, 0);
+        assert_eq!(hdr.max_frame_light, 0);
+
+        // Reordered, and without the luminances
+        let settings =
+            "master-display=WP(15635,16450)R(35400,14600)G(8500,39850)B(6550,2300) / cll=944,143";
+        let hdr = parse_x265_settings(settings).unwrap();
+        assert_eq!(hdr.color_coords.unwrap().green, (0.17, 0.797));
+        assert_eq!(hdr.max_luma, 0.);
+        assert_eq!(hdr.max_content_light, 944);
+        assert_eq!(hdr.max_frame_light, 143);
+
+        assert!(parse_x265_settings("wpp / ctu=64").is_err());
+        assert!(parse_x265_settings("master-display=G(8500,39850)B(6550,2300) / cll=1,1").is_err());
+        assert!(parse_x265_settings("master-display=G(8500,B(6550,2300)").is_err());
+    }
+
+    #[test]
+    fn mediainfo_encoding_settings() {
+        let output = "Video\n\
+                      Encoding settings                        : wpp / master-display=G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,50) / cll=1000,400 / hdr10\n\
+                      Maximum Content Light Level              : 944 cd/m2\n";
+        let hdr = parse_mediainfo(output, 1).unwrap().hdr.unwrap();
+        assert_eq!(hdr.color_coords.unwrap().white, (0.3127, 0.329));
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.min_luma, 0.005);
+        // mediainfo's own field wins over the encoding settings
+        assert_eq!(hdr.max_content_light, 944);
+    }
+
+    #[test]
+    fn luminance_units_and_exponents() {
+        assert_eq!(parse_luminance("1000.00 cd/m2").unwrap(), 1000.);
+        assert_eq!(parse_luminance("1000.0000cd/m2").unwrap(), 1000.);
+        assert_eq!(parse_luminance("  1e3 cd/m2 ").unwrap(), 1000.);
+        assert_eq!(parse_luminance("5e-3 cd/m2").unwrap(), 0.005);
+        assert_eq!(parse_luminance("0.0001").unwrap(), 0.0001);
+        assert!(parse_luminance("cd/m2").is_err());
+
+        let output = "Video\n\
+                      Mastering display color primaries        : BT.2020\n\
+                      Mastering display luminance              : min: 5e-3 cd/m2, max: 1000.00 cd/m2\n";
+        let hdr = parse_mediainfo(output, 1).unwrap().hdr.unwrap();
+        assert_eq!(hdr.min_luma, 0.005);
+        assert_eq!(hdr.max_luma, 1000.);
     }
-    Ok(Some(hdr))
 }
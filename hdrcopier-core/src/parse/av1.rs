@@ -0,0 +1,347 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::Result;
+
+use super::bits::BitReader;
+use crate::{
+    metadata::{BasicMetadata, ColorCoordinates, Metadata},
+    values::{ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics},
+};
+
+// OBU types, from the AV1 spec
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const OBU_FRAME_HEADER: u8 = 3;
+const OBU_METADATA: u8 = 5;
+const OBU_FRAME: u8 = 6;
+
+// Metadata OBU types
+const METADATA_TYPE_HDR_CLL: u64 = 1;
+const METADATA_TYPE_HDR_MDCV: u64 = 2;
+
+const IVF_SIGNATURE: &[u8] = b"DKIF";
+const IVF_FRAME_HEADER_SIZE: usize = 12;
+
+// The sequence header and HDR metadata come before the first frame
+const MAX_HEADER_SIZE: u64 = 4 * 1024 * 1024;
+
+// Reads the colour metadata from the sequence header and metadata OBUs at the start
+// of a raw AV1 stream, either as bare OBUs or in an IVF file, such as an encoder writes
+// before muxing.
+pub fn parse_av1(input: &Path) -> Result<Option<Metadata>> {
+    let mut data = Vec::new();
+    File::open(input)?
+        .take(MAX_HEADER_SIZE)
+        .read_to_end(&mut data)?;
+    parse_av1_data(&data)
+}
+
+fn parse_av1_data(data: &[u8]) -> Result<Option<Metadata>> {
+    let mut metadata = Metadata::default();
+    if data.starts_with(IVF_SIGNATURE) {
+        if data.len() < 32 || &data[8..12] != b"AV01" {
+            return Ok(None);
+        }
+        let mut pos = u16::from_le_bytes([data[6], data[7]]) as usize;
+        while pos + IVF_FRAME_HEADER_SIZE <= data.len() {
+            let size = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let start = pos + IVF_FRAME_HEADER_SIZE;
+            let end = (start + size).min(data.len());
+            if parse_obus(&data[start..end], &mut metadata)? {
+                break;
+            }
+            pos = end;
+        }
+    } else {
+        parse_obus(data, &mut metadata)?;
+    }
+
+    if metadata.basic.is_none() && metadata.hdr.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(metadata))
+}
+
+// Returns true once the first frame is reached, since everything we need comes before it
+fn parse_obus(data: &[u8], metadata: &mut Metadata) -> Result<bool> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0x0F;
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        pos += 1 + has_extension as usize;
+        let size = if has_size {
+            let (size, len) = read_leb128(&data[pos.min(data.len())..])?;
+            pos += len;
+            size as usize
+        } else {
+            data.len().saturating_sub(pos)
+        };
+        let end = pos + size;
+        if end > data.len() {
+            // Only the first frame is ever large enough to be cut off by `MAX_HEADER_SIZE`,
+            // and it's where we stop anyway
+            if matches!(obu_type, OBU_FRAME_HEADER | OBU_FRAME) {
+                return Ok(true);
+            }
+            anyhow::bail!("AV1 stream ends partway through an OBU");
+        }
+        let payload = &data[pos..end];
+        match obu_type {
+            OBU_SEQUENCE_HEADER if metadata.basic.is_none() => {
+                metadata.basic = parse_sequence_header(payload)?;
+            }
+            OBU_METADATA => parse_metadata_obu(payload, metadata)?,
+            OBU_FRAME_HEADER | OBU_FRAME => return Ok(true),
+            _ => (),
+        }
+        pos = end;
+    }
+    Ok(false)
+}
+
+fn read_leb128(data: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    anyhow::bail!("Invalid OBU size")
+}
+
+fn parse_metadata_obu(data: &[u8], metadata: &mut Metadata) -> Result<()> {
+    let (metadata_type, len) = read_leb128(data)?;
+    let payload = &data[len..];
+    let value = |index: usize| u16::from_be_bytes([payload[index], payload[index + 1]]) as u32;
+    match metadata_type {
+        METADATA_TYPE_HDR_CLL if payload.len() >= 4 => {
+            let hdr = metadata.hdr.get_or_insert_with(Default::default);
+            hdr.max_content_light = value(0);
+            hdr.max_frame_light = value(2);
+        }
+        METADATA_TYPE_HDR_MDCV if payload.len() >= 24 => {
+            // Chromaticities are 0.16 fixed point, and unlike HEVC
+            // the primaries are in red, green, blue order
+            let point = |index: usize| {
+                (
+                    value(index) as f64 / 65536.,
+                    value(index + 2) as f64 / 65536.,
+                )
+            };
+            let luminance = |index: usize| {
+                u32::from_be_bytes(payload[index..index + 4].try_into().unwrap()) as f64
+            };
+            let hdr = metadata.hdr.get_or_insert_with(Default::default);
+            hdr.color_coords = Some(ColorCoordinates {
+                red: point(0),
+                green: point(4),
+                blue: point(8),
+                white: point(12),
+            });
+            // The maximum is 24.8 fixed point, and the minimum is 18.14
+            hdr.max_luma = luminance(16) / 256.;
+            hdr.min_luma = luminance(20) / 16384.;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+// The colour config comes after every other field of the sequence header
+fn parse_sequence_header(data: &[u8]) -> Result<Option<BasicMetadata>> {
+    let mut reader = BitReader::new(data);
+    let profile = reader.read_bits(3)?;
+    reader.skip_bits(1)?; // still_picture
+    let reduced_still_picture_header = reader.read_bit()?;
+    if reduced_still_picture_header {
+        reader.skip_bits(5)?; // seq_level_idx
+    } else {
+        let mut decoder_model_info_present = false;
+        let mut buffer_delay_length = 0;
+        if reader.read_bit()? {
+            // timing_info
+            reader.skip_bits(64)?;
+            if reader.read_bit()? {
+                reader.read_ue()?; // num_ticks_per_picture_minus_1, as uvlc
+            }
+            decoder_model_info_present = reader.read_bit()?;
+            if decoder_model_info_present {
+                buffer_delay_length = reader.read_bits(5)? as usize + 1;
+                reader.skip_bits(42)?; // num_units_in_decoding_tick, and two lengths
+            }
+        }
+        let initial_display_delay_present = reader.read_bit()?;
+        let operating_points = reader.read_bits(5)? + 1;
+        for _ in 0..operating_points {
+            reader.skip_bits(12)?; // operating_point_idc
+            if reader.read_bits(5)? > 7 {
+                reader.skip_bits(1)?; // seq_tier
+            }
+            if decoder_model_info_present && reader.read_bit()? {
+                // decoder_buffer_delay, encoder_buffer_delay, low_delay_mode_flag
+                reader.skip_bits(2 * buffer_delay_length + 1)?;
+            }
+            if initial_display_delay_present && reader.read_bit()? {
+                reader.skip_bits(4)?;
+            }
+        }
+    }
+    let width_bits = reader.read_bits(4)? as usize + 1;
+    let height_bits = reader.read_bits(4)? as usize + 1;
+    reader.skip_bits(width_bits + height_bits)?;
+    if !reduced_still_picture_header && reader.read_bit()? {
+        reader.skip_bits(7)?; // frame ID lengths
+    }
+    // use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+    reader.skip_bits(3)?;
+    if !reduced_still_picture_header {
+        // enable_interintra_compound, enable_masked_compound,
+        // enable_warped_motion, enable_dual_filter
+        reader.skip_bits(4)?;
+        let enable_order_hint = reader.read_bit()?;
+        if enable_order_hint {
+            reader.skip_bits(2)?; // enable_jnt_comp, enable_ref_frame_mvs
+        }
+        let force_screen_content_tools = reader.read_bit()? || reader.read_bit()?;
+        if force_screen_content_tools && !reader.read_bit()? {
+            reader.skip_bits(1)?; // seq_force_integer_mv
+        }
+        if enable_order_hint {
+            reader.skip_bits(3)?; // order_hint_bits_minus_1
+        }
+    }
+    // enable_superres, enable_cdef, enable_restoration
+    reader.skip_bits(3)?;
+
+    // color_config
+    let high_bitdepth = reader.read_bit()?;
+    if profile == 2 && high_bitdepth {
+        reader.skip_bits(1)?; // twelve_bit
+    }
+    let mono_chrome = profile != 1 && reader.read_bit()?;
+    if !reader.read_bit()? {
+        return Ok(None);
+    }
+    let primaries = reader.read_bits(8)? as u8;
+    let transfer = reader.read_bits(8)? as u8;
+    let matrix = reader.read_bits(8)? as u8;
+    // sRGB has no range flag, since it's always full range
+    let full_range = if !mono_chrome && primaries == 1 && transfer == 13 && matrix == 0 {
+        true
+    } else {
+        reader.read_bit()?
+    };
+    Ok(Some(BasicMetadata {
+        matrix: Some(MatrixCoefficients::from_u8(matrix)),
+        range: Some(if full_range {
+            ColorRange::Full
+        } else {
+            ColorRange::Limited
+        }),
+        transfer: Some(TransferCharacteristics::from_u8(transfer)),
+        primaries: Some(ColorPrimaries::from_u8(primaries)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::chromaticity_to_units;
+
+    // The start of a 3840x2160 10 bit stream with BT.2020 PQ colours, and the mastering
+    // display G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,50)
+    // with content light levels of 1000,400
+    #[rustfmt::skip]
+    const OBUS: &[u8] = &[
+        // Temporal delimiter
+        0x12, 0x00,
+        // Sequence header
+        0x0a, 0x0f, 0x00, 0x00, 0x00, 0x6d, 0xdf, 0x7f, 0xc3, 0x79, 0xff, 0xf3,
+        0xa1, 0x22, 0x01, 0x20, 0x80,
+        // Content light level metadata
+        0x2a, 0x05, 0x01, 0x03, 0xe8, 0x01, 0x90,
+        // Mastering display metadata
+        0x2a, 0x19, 0x02, 0xb5, 0x3f, 0x4a, 0xc1, 0x2b, 0x85, 0xcc, 0x08, 0x21,
+        0x89, 0x0b, 0xc7, 0x50, 0x0d, 0x54, 0x39, 0x00, 0x03, 0xe8, 0x00, 0x00,
+        0x00, 0x00, 0x52,
+        // The first frame
+        0x32, 0x04, 0x10, 0x00, 0x00, 0x80,
+    ];
+
+    fn ivf(frame: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DKIF");
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&32u16.to_le_bytes());
+        data.extend_from_slice(b"AV01");
+        data.extend_from_slice(&3840u16.to_le_bytes());
+        data.extend_from_slice(&2160u16.to_le_bytes());
+        data.extend_from_slice(&[24, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(frame);
+        data
+    }
+
+    fn check(metadata: Metadata) {
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Pq));
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+        assert_eq!(basic.range, Some(ColorRange::Limited));
+        let hdr = metadata.hdr.unwrap();
+        let coords = hdr.color_coords.unwrap();
+        let units = |(x, y): (f64, f64)| (chromaticity_to_units(x), chromaticity_to_units(y));
+        assert_eq!(units(coords.green), (8500, 39850));
+        assert_eq!(units(coords.blue), (6550, 2300));
+        assert_eq!(units(coords.red), (35400, 14600));
+        assert_eq!(units(coords.white), (15635, 16450));
+        assert_eq!(hdr.max_luma, 1000.);
+        // 0.005 isn't exact in 18.14 fixed point
+        assert_eq!((hdr.min_luma * 10000.).round(), 50.);
+        assert_eq!(hdr.max_content_light, 1000);
+        assert_eq!(hdr.max_frame_light, 400);
+    }
+
+    #[test]
+    fn sequence_header_and_metadata() {
+        check(parse_av1_data(OBUS).unwrap().unwrap());
+        check(parse_av1_data(&ivf(OBUS)).unwrap().unwrap());
+    }
+
+    #[test]
+    fn truncated_obus() {
+        assert!(parse_av1_data(&[]).unwrap().is_none());
+        // Cut off partway through the sequence header or the metadata
+        let truncated = "AV1 stream ends partway through an OBU";
+        for data in [&OBUS[..12], &OBUS[..40], &ivf(OBUS)[..50]] {
+            let err = parse_av1_data(data).err().unwrap();
+            assert_eq!(err.to_string(), truncated);
+        }
+        // but a frame that's cut off doesn't matter
+        check(parse_av1_data(&OBUS[..OBUS.len() - 2]).unwrap().unwrap());
+        // and neither does an IVF header without any frames
+        assert!(parse_av1_data(&ivf(OBUS)[..20]).unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupt_obus() {
+        // An OBU size which doesn't end within the 8 bytes that LEB128 may have
+        let err = parse_av1_data(&[0x0a, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80])
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Invalid OBU size");
+
+        // An IVF frame which says it's larger than the file
+        let mut data = ivf(OBUS);
+        data[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        check(parse_av1_data(&data).unwrap().unwrap());
+
+        // An IVF file of another codec
+        let mut data = ivf(OBUS);
+        data[8..12].copy_from_slice(b"VP90");
+        assert!(parse_av1_data(&data).unwrap().is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+
+// Reads big-endian bit fields, as used by video bitstream headers
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    // Position in bits from the start of `data`
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    // Reads up to 32 bits as an unsigned integer
+    pub fn read_bits(&mut self, count: u32) -> Result<u32> {
+        debug_assert!(count <= 32);
+        if self.position + count as usize > self.data.len() * 8 {
+            anyhow::bail!("Unexpected end of bitstream header");
+        }
+        let mut value = 0u64;
+        for _ in 0..count {
+            let byte = self.data[self.position / 8];
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u64;
+            self.position += 1;
+        }
+        Ok(value as u32)
+    }
+
+    pub fn skip_bits(&mut self, count: usize) -> Result<()> {
+        if self.position + count > self.data.len() * 8 {
+            anyhow::bail!("Unexpected end of bitstream header");
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    // Unsigned Exp-Golomb code, ue(v) in the H.264 and HEVC specs
+    pub fn read_ue(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                anyhow::bail!("Invalid Exp-Golomb code in bitstream header");
+            }
+        }
+        Ok((1u64 << leading_zeros) as u32 - 1 + self.read_bits(leading_zeros)?)
+    }
+
+    // Signed Exp-Golomb code, se(v) in the H.264 and HEVC specs
+    pub fn read_se(&mut self) -> Result<i32> {
+        let value = self.read_ue()? as i64;
+        Ok(if value % 2 == 1 {
+            ((value + 1) / 2) as i32
+        } else {
+            -(value / 2) as i32
+        })
+    }
+}
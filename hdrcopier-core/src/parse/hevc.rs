@@ -0,0 +1,449 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::Result;
+
+use super::bits::BitReader;
+use crate::{
+    metadata::{chromaticity_from_units, BasicMetadata, ColorCoordinates, HdrMetadata, Metadata},
+    values::{ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics},
+};
+
+// NAL unit types, from the HEVC spec (ITU-T H.265)
+const NAL_SPS: u8 = 33;
+const NAL_PREFIX_SEI: u8 = 39;
+// Types below this are slices, which come after the headers we need
+const NAL_FIRST_NON_VCL: u8 = 32;
+
+// SEI payload types
+const SEI_MASTERING_DISPLAY: u32 = 137;
+const SEI_CONTENT_LIGHT_LEVEL: u32 = 144;
+
+// The parameter sets and SEI are at the very start of the stream, before the first slice
+const MAX_HEADER_SIZE: u64 = 4 * 1024 * 1024;
+
+// Reads the colour metadata from the parameter sets and SEI messages at the start of
+// a raw HEVC (Annex B) stream, such as an encoder writes before muxing.
+pub fn parse_hevc(input: &Path) -> Result<Option<Metadata>> {
+    let mut data = Vec::new();
+    File::open(input)?
+        .take(MAX_HEADER_SIZE)
+        .read_to_end(&mut data)?;
+    parse_hevc_data(&data)
+}
+
+fn parse_hevc_data(data: &[u8]) -> Result<Option<Metadata>> {
+    let mut metadata = Metadata::default();
+    let mut hdr: Option<HdrMetadata> = None;
+    for nal in nal_units(data) {
+        if nal.len() < 2 {
+            continue;
+        }
+        let nal_type = (nal[0] >> 1) & 0x3F;
+        if nal_type < NAL_FIRST_NON_VCL {
+            break;
+        }
+        let payload = remove_emulation_prevention(&nal[2..]);
+        match nal_type {
+            NAL_SPS if metadata.basic.is_none() => {
+                metadata.basic = parse_sps(&payload)?;
+            }
+            NAL_PREFIX_SEI => {
+                parse_sei(&payload, hdr.get_or_insert_with(Default::default))?;
+            }
+            _ => (),
+        }
+    }
+    // SEI messages we don't care about leave an empty struct behind
+    metadata.hdr = hdr.filter(|hdr| *hdr != HdrMetadata::default());
+
+    if metadata.basic.is_none() && metadata.hdr.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(metadata))
+}
+
+// Splits an Annex B stream on its start codes
+fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    (0..starts.len()).map(move |n| {
+        let end = starts.get(n + 1).map_or(data.len(), |&next| next - 3);
+        // A four byte start code leaves a zero at the end of the previous unit
+        let mut unit = &data[starts[n]..end];
+        while let Some((&0, rest)) = unit.split_last() {
+            unit = rest;
+        }
+        unit
+    })
+}
+
+// Encoders insert a 0x03 after any two zero bytes in a NAL unit, so that
+// its contents can't be mistaken for a start code
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        output.push(byte);
+    }
+    output
+}
+
+fn parse_sei(data: &[u8], hdr: &mut HdrMetadata) -> Result<()> {
+    let mut pos = 0;
+    // The last byte is the RBSP trailing bits
+    while pos + 1 < data.len() {
+        let (payload_type, len) = read_sei_value(&data[pos..]);
+        pos += len;
+        let (payload_size, len) = read_sei_value(&data[pos..]);
+        pos += len;
+        let end = pos + payload_size as usize;
+        if end > data.len() {
+            anyhow::bail!("SEI message runs past the end of its NAL unit");
+        }
+        let payload = &data[pos..end];
+        match payload_type {
+            SEI_MASTERING_DISPLAY if payload.len() >= 24 => {
+                let value = |index: usize| u16::from_be_bytes([payload[index], payload[index + 1]]);
+                let point = |index: usize| {
+                    (
+                        chromaticity_from_units(value(index) as u32),
+                        chromaticity_from_units(value(index + 2) as u32),
+                    )
+                };
+                // The primaries are in the same green, blue, red order as x265's master display
+                hdr.color_coords = Some(ColorCoordinates {
+                    green: point(0),
+                    blue: point(4),
+                    red: point(8),
+                    white: point(12),
+                });
+                let luminance = |index: usize| {
+                    u32::from_be_bytes(payload[index..index + 4].try_into().unwrap()) as f64
+                        / 10000.
+                };
+                hdr.max_luma = luminance(16);
+                hdr.min_luma = luminance(20);
+            }
+            SEI_CONTENT_LIGHT_LEVEL if payload.len() >= 4 => {
+                hdr.max_content_light = u16::from_be_bytes([payload[0], payload[1]]) as u32;
+                hdr.max_frame_light = u16::from_be_bytes([payload[2], payload[3]]) as u32;
+            }
+            _ => (),
+        }
+        pos = end;
+    }
+    Ok(())
+}
+
+// SEI types and sizes are written as a run of 0xFF bytes, each adding 255, then a final byte
+fn read_sei_value(data: &[u8]) -> (u32, usize) {
+    let mut value = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        value += byte as u32;
+        if byte != 0xFF {
+            return (value, i + 1);
+        }
+    }
+    (value, data.len())
+}
+
+// The colour description is in the VUI at the very end of the SPS,
+// so everything before it has to be read just to find where it starts
+fn parse_sps(data: &[u8]) -> Result<Option<BasicMetadata>> {
+    let mut reader = BitReader::new(data);
+    reader.skip_bits(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.read_bits(3)?;
+    reader.skip_bits(1)?; // sps_temporal_id_nesting_flag
+    skip_profile_tier_level(&mut reader, max_sub_layers_minus1)?;
+    reader.read_ue()?; // sps_seq_parameter_set_id
+    if reader.read_ue()? == 3 {
+        reader.skip_bits(1)?; // separate_colour_plane_flag
+    }
+    reader.read_ue()?; // pic_width_in_luma_samples
+    reader.read_ue()?; // pic_height_in_luma_samples
+    if reader.read_bit()? {
+        // conformance window offsets
+        for _ in 0..4 {
+            reader.read_ue()?;
+        }
+    }
+    reader.read_ue()?; // bit_depth_luma_minus8
+    reader.read_ue()?; // bit_depth_chroma_minus8
+    let log2_max_poc_lsb = reader.read_ue()? + 4;
+    let ordering_info_present = reader.read_bit()?;
+    let first_sub_layer = if ordering_info_present {
+        0
+    } else {
+        max_sub_layers_minus1
+    };
+    for _ in first_sub_layer..=max_sub_layers_minus1 {
+        reader.read_ue()?; // sps_max_dec_pic_buffering_minus1
+        reader.read_ue()?; // sps_max_num_reorder_pics
+        reader.read_ue()?; // sps_max_latency_increase_plus1
+    }
+    // Coding block and transform sizes
+    for _ in 0..6 {
+        reader.read_ue()?;
+    }
+    if reader.read_bit()? && reader.read_bit()? {
+        skip_scaling_list_data(&mut reader)?;
+    }
+    reader.skip_bits(2)?; // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    if reader.read_bit()? {
+        // PCM sample bit depths, sizes, and loop filter flag
+        reader.skip_bits(8)?;
+        reader.read_ue()?;
+        reader.read_ue()?;
+        reader.skip_bits(1)?;
+    }
+    let num_short_term_ref_pic_sets = reader.read_ue()?;
+    // The spec allows at most 64, so anything more is a corrupt header
+    if num_short_term_ref_pic_sets > 64 {
+        anyhow::bail!("Invalid number of reference picture sets in HEVC SPS");
+    }
+    let mut num_delta_pocs = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+    for index in 0..num_short_term_ref_pic_sets as usize {
+        let count = read_short_term_ref_pic_set(&mut reader, index, &num_delta_pocs)?;
+        num_delta_pocs.push(count);
+    }
+    if reader.read_bit()? {
+        for _ in 0..reader.read_ue()? {
+            reader.skip_bits(log2_max_poc_lsb as usize + 1)?;
+        }
+    }
+    reader.skip_bits(2)?; // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+    if !reader.read_bit()? {
+        return Ok(None);
+    }
+
+    // VUI
+    if reader.read_bit()? && reader.read_bits(8)? == 255 {
+        reader.skip_bits(32)?; // sar_width, sar_height
+    }
+    if reader.read_bit()? {
+        reader.skip_bits(1)?; // overscan_appropriate_flag
+    }
+    if !reader.read_bit()? {
+        return Ok(None);
+    }
+    reader.skip_bits(3)?; // video_format
+    let full_range = reader.read_bit()?;
+    if !reader.read_bit()? {
+        return Ok(None);
+    }
+    let primaries = reader.read_bits(8)? as u8;
+    let transfer = reader.read_bits(8)? as u8;
+    let matrix = reader.read_bits(8)? as u8;
+    Ok(Some(BasicMetadata {
+        matrix: Some(MatrixCoefficients::from_u8(matrix)),
+        range: Some(if full_range {
+            ColorRange::Full
+        } else {
+            ColorRange::Limited
+        }),
+        transfer: Some(TransferCharacteristics::from_u8(transfer)),
+        primaries: Some(ColorPrimaries::from_u8(primaries)),
+    }))
+}
+
+fn skip_profile_tier_level(reader: &mut BitReader, max_sub_layers_minus1: u32) -> Result<()> {
+    // General profile, tier, constraint flags, and level
+    reader.skip_bits(96)?;
+    let mut sub_layers = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((reader.read_bit()?, reader.read_bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip_bits(2 * (8 - max_sub_layers_minus1 as usize))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            reader.skip_bits(88)?;
+        }
+        if level_present {
+            reader.skip_bits(8)?;
+        }
+    }
+    Ok(())
+}
+
+fn skip_scaling_list_data(reader: &mut BitReader) -> Result<()> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if !reader.read_bit()? {
+                reader.read_ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+            let coefficients = 64.min(1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                reader.read_se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coefficients {
+                reader.read_se()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Returns the number of pictures in the set, which later sets may be predicted from
+fn read_short_term_ref_pic_set(
+    reader: &mut BitReader,
+    index: usize,
+    num_delta_pocs: &[u32],
+) -> Result<u32> {
+    if index != 0 && reader.read_bit()? {
+        // Predicted from the previous set
+        reader.skip_bits(1)?; // delta_rps_sign
+        reader.read_ue()?; // abs_delta_rps_minus1
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[index - 1] {
+            let used_by_curr_pic = reader.read_bit()?;
+            if used_by_curr_pic || reader.read_bit()? {
+                count += 1;
+            }
+        }
+        return Ok(count);
+    }
+    let num_negative_pics = reader.read_ue()?;
+    let num_positive_pics = reader.read_ue()?;
+    // Each is limited by the decoded picture buffer size, which is at most 16
+    if num_negative_pics > 16 || num_positive_pics > 16 {
+        anyhow::bail!("Invalid reference picture set in HEVC SPS");
+    }
+    for _ in 0..num_negative_pics + num_positive_pics {
+        reader.read_ue()?; // delta_poc_minus1
+        reader.skip_bits(1)?; // used_by_curr_pic_flag
+    }
+    Ok(num_negative_pics + num_positive_pics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The headers of a 3840x2160 Main 10 stream, laid out as x265 writes them with
+    // `--hdr10 --colorprim bt2020 --transfer smpte2084 --colormatrix bt2020nc --master-display
+    // G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1) --max-cll 1000,400`
+    #[rustfmt::skip]
+    const X265_HEADERS: &[u8] = &[
+        // VPS
+        0x00, 0x00, 0x00, 0x01, 0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x02, 0x20,
+        0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00,
+        0x99, 0x95, 0x94, 0x09,
+        // SPS
+        0x00, 0x00, 0x00, 0x01, 0x42, 0x01, 0x01, 0x02, 0x20, 0x00, 0x00, 0x03,
+        0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x99, 0xa0, 0x01,
+        0xe0, 0x20, 0x02, 0x1c, 0x4d, 0x96, 0x57, 0x92, 0x44, 0x8a, 0xf0, 0x16,
+        0xa1, 0x22, 0x01, 0x36, 0xc2, 0x00, 0x00, 0x07, 0xd2, 0x00, 0x00, 0xbb,
+        0x80, 0x10,
+        // PPS
+        0x00, 0x00, 0x00, 0x01, 0x44, 0x01, 0xc1, 0x72, 0x1c, 0xb8, 0xa2, 0x40,
+        // SEI: x265's version string, then the mastering display, then the content light levels
+        0x00, 0x00, 0x00, 0x01, 0x4e, 0x01, 0x05, 0x49, 0x2c, 0xa2, 0xde, 0x09,
+        0xb5, 0x17, 0x47, 0xdb, 0xbb, 0x55, 0xa4, 0xfe, 0x7f, 0xc2, 0xfc, 0x4e,
+        0x78, 0x32, 0x36, 0x35, 0x20, 0x28, 0x62, 0x75, 0x69, 0x6c, 0x64, 0x20,
+        0x31, 0x39, 0x39, 0x29, 0x20, 0x2d, 0x20, 0x33, 0x2e, 0x35, 0x3a, 0x5b,
+        0x4c, 0x69, 0x6e, 0x75, 0x78, 0x5d, 0x5b, 0x47, 0x43, 0x43, 0x20, 0x31,
+        0x31, 0x2e, 0x32, 0x2e, 0x30, 0x5d, 0x5b, 0x36, 0x34, 0x20, 0x62, 0x69,
+        0x74, 0x5d, 0x20, 0x31, 0x30, 0x62, 0x69, 0x74, 0x00, 0x80, 0x00, 0x00,
+        0x00, 0x01, 0x4e, 0x01, 0x89, 0x18, 0x21, 0x34, 0x9b, 0xaa, 0x19, 0x96,
+        0x08, 0xfc, 0x8a, 0x48, 0x39, 0x08, 0x3d, 0x13, 0x40, 0x42, 0x00, 0x98,
+        0x96, 0x80, 0x00, 0x00, 0x03, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00, 0x01,
+        0x4e, 0x01, 0x90, 0x04, 0x03, 0xe8, 0x01, 0x90, 0x80,
+        // The first slice
+        0x00, 0x00, 0x00, 0x01, 0x28, 0x01, 0xaf, 0x08, 0x40,
+    ];
+
+    #[test]
+    fn x265_headers() {
+        let metadata = parse_hevc_data(X265_HEADERS).unwrap().unwrap();
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Pq));
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Bt2020Ncl));
+        assert_eq!(basic.range, Some(ColorRange::Limited));
+        let hdr = metadata.hdr.unwrap();
+        let coords = hdr.color_coords.unwrap();
+        assert_eq!(coords.green, (0.17, 0.797));
+        assert_eq!(coords.blue, (0.131, 0.046));
+        assert_eq!(coords.red, (0.708, 0.292));
+        assert_eq!(coords.white, (0.3127, 0.329));
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.min_luma, 0.0001);
+        assert_eq!(hdr.max_content_light, 1000);
+        assert_eq!(hdr.max_frame_light, 400);
+    }
+
+    // The SPS of a 64x64 stream, up to where its reference picture sets are counted
+    #[rustfmt::skip]
+    const SPS_BEFORE_REF_PIC_SETS: &[u8] = &[
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xa0, 0x20, 0x81, 0x05, 0xff, 0xf0,
+    ];
+
+    // Where the mastering display SEI starts, after its NAL unit header
+    fn mastering_display_sei() -> usize {
+        X265_HEADERS
+            .windows(4)
+            .position(|bytes| bytes == [0x4e, 0x01, 0x89, 0x18])
+            .unwrap()
+            + 2
+    }
+
+    #[test]
+    fn truncated_headers() {
+        assert!(parse_hevc_data(&[]).unwrap().is_none());
+        // Cut off partway through the SPS
+        assert!(parse_hevc_data(&X265_HEADERS[..60]).is_err());
+        // Cut off partway through the mastering display
+        let err = parse_hevc_data(&X265_HEADERS[..mastering_display_sei() + 10])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "SEI message runs past the end of its NAL unit"
+        );
+    }
+
+    #[test]
+    fn sei_size_past_end_of_nal_unit() {
+        let mut data = X265_HEADERS.to_vec();
+        data[mastering_display_sei() + 1] = 0x40;
+        let err = parse_hevc_data(&data).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "SEI message runs past the end of its NAL unit"
+        );
+    }
+
+    #[test]
+    fn invalid_ref_pic_sets() {
+        // 65 sets, one more than the spec allows
+        let sps = [SPS_BEFORE_REF_PIC_SETS, &[0x02, 0x14]].concat();
+        assert_eq!(
+            parse_sps(&sps).err().unwrap().to_string(),
+            "Invalid number of reference picture sets in HEVC SPS"
+        );
+        // One set, with 17 pictures before the current one
+        let sps = [SPS_BEFORE_REF_PIC_SETS, &[0x41, 0x2c]].concat();
+        assert_eq!(
+            parse_sps(&sps).err().unwrap().to_string(),
+            "Invalid reference picture set in HEVC SPS"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+    metadata::{chromaticity_from_units, BasicMetadata, ColorCoordinates, HdrMetadata, Metadata},
+    values::{ColorPrimaries, ColorRange, MatrixCoefficients, TransferCharacteristics},
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXR_MAGIC: &[u8] = &[0x76, 0x2F, 0x31, 0x01];
+
+// The colour chunks of a PNG and the header of an EXR come before the pixels
+const MAX_HEADER_SIZE: u64 = 4 * 1024 * 1024;
+
+// The chunks read by `parse_png`
+const COLOUR_CHUNKS: &[&[u8]] = &[
+    b"cICP", b"sRGB", b"cHRM", b"gAMA", b"mDCV", b"mDCv", b"cLLI", b"cLLi",
+];
+
+// gAMA stores the gamma times 100000, and a gamma of 1 means linear light
+const PNG_LINEAR_GAMMA: u32 = 100_000;
+
+// Reads the colour metadata from the chunks of a PNG image. cICP gives the codes
+// directly, and takes precedence over the older chunks, which only describe the
+// primaries and gamma.
+pub fn parse_png(input: &Path) -> Result<Option<Metadata>> {
+    parse_png_data(&read_header(input)?)
+}
+
+fn parse_png_data(data: &[u8]) -> Result<Option<Metadata>> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Ok(None);
+    }
+
+    let mut cicp = None;
+    let mut srgb = false;
+    let mut chrm = None;
+    let mut gamma = None;
+    let mut hdr = HdrMetadata::default();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let size = read_u32(&data[pos..]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let start = pos + 8;
+        let end = start + size;
+        if chunk_type == b"IDAT" || chunk_type == b"IEND" {
+            break;
+        }
+        if end > data.len() {
+            // Other chunks, such as an embedded ICC profile, can be large enough
+            // to be cut off by `MAX_HEADER_SIZE`, but we don't need them
+            if COLOUR_CHUNKS.contains(&chunk_type) {
+                anyhow::bail!(
+                    "PNG ends partway through its {} chunk",
+                    String::from_utf8_lossy(chunk_type)
+                );
+            }
+            break;
+        }
+        let chunk = &data[start..end];
+        match chunk_type {
+            b"cICP" if size >= 4 => cicp = Some([chunk[0], chunk[1], chunk[2], chunk[3]]),
+            b"sRGB" => srgb = true,
+            // cHRM has the white point first, and each value is times 100000
+            b"cHRM" if size >= 32 => {
+                let point = |index: usize| {
+                    (
+                        read_u32(&chunk[index..]) as f64 / 100_000.,
+                        read_u32(&chunk[index + 4..]) as f64 / 100_000.,
+                    )
+                };
+                chrm = Some(ColorCoordinates {
+                    white: point(0),
+                    red: point(8),
+                    green: point(16),
+                    blue: point(24),
+                });
+            }
+            b"gAMA" if size >= 4 => gamma = Some(read_u32(chunk)),
+            // These were named mDCv and cLLi in drafts of the spec,
+            // which some encoders still write
+            b"mDCV" | b"mDCv" if size >= 24 => {
+                let point = |index: usize| {
+                    (
+                        chromaticity_from_units(read_u16(&chunk[index..]) as u32),
+                        chromaticity_from_units(read_u16(&chunk[index + 2..]) as u32),
+                    )
+                };
+                hdr.color_coords = Some(ColorCoordinates {
+                    red: point(0),
+                    green: point(4),
+                    blue: point(8),
+                    white: point(12),
+                });
+                hdr.max_luma = read_u32(&chunk[16..]) as f64 / 10000.;
+                hdr.min_luma = read_u32(&chunk[20..]) as f64 / 10000.;
+            }
+            // Light levels are in units of 0.0001 cd/m²
+            b"cLLI" | b"cLLi" if size >= 8 => {
+                hdr.max_content_light = (read_u32(chunk) as f64 / 10000.).round() as u32;
+                hdr.max_frame_light = (read_u32(&chunk[4..]) as f64 / 10000.).round() as u32;
+            }
+            _ => (),
+        }
+        // Skip the CRC
+        pos = end + 4;
+    }
+
+    let transfer = match gamma {
+        Some(PNG_LINEAR_GAMMA) => Some(TransferCharacteristics::Linear),
+        _ => None,
+    };
+    let basic = if let Some([primaries, transfer, matrix, full_range]) = cicp {
+        Some(BasicMetadata {
+            primaries: Some(ColorPrimaries::from_u8(primaries)),
+            transfer: Some(TransferCharacteristics::from_u8(transfer)),
+            matrix: Some(MatrixCoefficients::from_u8(matrix)),
+            range: Some(if full_range == 1 {
+                ColorRange::Full
+            } else {
+                ColorRange::Limited
+            }),
+        })
+    } else if srgb {
+        Some(rgb_metadata(
+            Some(ColorPrimaries::Bt709),
+            Some(TransferCharacteristics::Srgb),
+        ))
+    } else if chrm.is_some() || transfer.is_some() {
+        let primaries = chrm.as_ref().map(ColorCoordinates::primaries);
+        Some(rgb_metadata(primaries, transfer))
+    } else {
+        None
+    };
+
+    let metadata = Metadata {
+        basic,
+        hdr: Some(hdr).filter(|hdr| *hdr != HdrMetadata::default()),
+        ..Default::default()
+    };
+    if metadata.basic.is_none() && metadata.hdr.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(metadata))
+}
+
+// Reads the primaries from the chromaticities attribute in the header of an OpenEXR
+// image. EXR pixels are always linear light, and the spec says that images without
+// the attribute use the BT.709 primaries, so this always finds something.
+pub fn parse_exr(input: &Path) -> Result<Option<Metadata>> {
+    parse_exr_data(&read_header(input)?)
+}
+
+fn parse_exr_data(data: &[u8]) -> Result<Option<Metadata>> {
+    if !data.starts_with(EXR_MAGIC) {
+        return Ok(None);
+    }
+
+    // After the magic number and version, each attribute is its name and type
+    // as null-terminated strings, then the size of its value and the value itself.
+    // An empty name ends the header. Multi-part files have a header for each part,
+    // but the first is enough.
+    let mut primaries = ColorPrimaries::Bt709;
+    let mut pos = 8;
+    loop {
+        let (name, len) = read_string(&data[pos.min(data.len())..])?;
+        if name.is_empty() {
+            break;
+        }
+        pos += len;
+        let (attribute_type, len) = read_string(&data[pos.min(data.len())..])?;
+        pos += len;
+        if pos + 4 > data.len() {
+            anyhow::bail!("EXR header is truncated");
+        }
+        let size = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        pos += 4;
+        let end = pos + size;
+        if end > data.len() {
+            anyhow::bail!("EXR header is truncated");
+        }
+        if name == b"chromaticities" && attribute_type == b"chromaticities" && size >= 32 {
+            let value = &data[pos..end];
+            let float = |index: usize| {
+                f32::from_le_bytes(value[index..index + 4].try_into().unwrap()) as f64
+            };
+            let coords = ColorCoordinates {
+                red: (float(0), float(4)),
+                green: (float(8), float(12)),
+                blue: (float(16), float(20)),
+                white: (float(24), float(28)),
+            };
+            primaries = coords.primaries();
+        }
+        pos = end;
+    }
+
+    Ok(Some(Metadata {
+        basic: Some(rgb_metadata(
+            Some(primaries),
+            Some(TransferCharacteristics::Linear),
+        )),
+        ..Default::default()
+    }))
+}
+
+fn read_header(input: &Path) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(input)?
+        .take(MAX_HEADER_SIZE)
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// Images are RGB, and always use the full range
+fn rgb_metadata(
+    primaries: Option<ColorPrimaries>,
+    transfer: Option<TransferCharacteristics>,
+) -> BasicMetadata {
+    BasicMetadata {
+        primaries,
+        transfer,
+        matrix: Some(MatrixCoefficients::Rgb),
+        range: Some(ColorRange::Full),
+    }
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[0], data[1]])
+}
+
+// Returns a null-terminated string without its terminator, and its length with it
+fn read_string(data: &[u8]) -> Result<(&[u8], usize)> {
+    let len = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| anyhow::anyhow!("EXR header is truncated"))?;
+    Ok((&data[..len], len + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::chromaticity_to_units;
+
+    // A 1x1 image with BT.2020 PQ colours, and the mastering display
+    // G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,50)
+    // with content light levels of 1000,400
+    #[rustfmt::skip]
+    const CICP_PNG: &[u8] = &[
+        // Signature
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a,
+        // IHDR
+        0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x90, 0x77, 0x53,
+        0xde,
+        // cICP
+        0x00, 0x00, 0x00, 0x04, 0x63, 0x49, 0x43, 0x50, 0x09, 0x10, 0x00, 0x01,
+        0x4d, 0x23, 0x23, 0xfe,
+        // mDCV
+        0x00, 0x00, 0x00, 0x18, 0x6d, 0x44, 0x43, 0x56, 0x8a, 0x48, 0x39, 0x08,
+        0x21, 0x34, 0x9b, 0xaa, 0x19, 0x96, 0x08, 0xfc, 0x3d, 0x13, 0x40, 0x42,
+        0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x32, 0x20, 0x8c, 0x00, 0xc8,
+        // cLLI
+        0x00, 0x00, 0x00, 0x08, 0x63, 0x4c, 0x4c, 0x49, 0x00, 0x98, 0x96, 0x80,
+        0x00, 0x3d, 0x09, 0x00, 0x55, 0x73, 0x11, 0xd0,
+        // IDAT
+        0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x60, 0x60, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0xf6, 0x17, 0x38, 0x55,
+        // IEND
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    // A 1x1 image with only the older chunks: BT.2020 chromaticities and a gamma of 1
+    #[rustfmt::skip]
+    const CHRM_PNG: &[u8] = &[
+        // Signature
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a,
+        // IHDR
+        0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x90, 0x77, 0x53,
+        0xde,
+        // cHRM
+        0x00, 0x00, 0x00, 0x20, 0x63, 0x48, 0x52, 0x4d, 0x00, 0x00, 0x7a, 0x26,
+        0x00, 0x00, 0x80, 0x84, 0x00, 0x01, 0x14, 0x90, 0x00, 0x00, 0x72, 0x10,
+        0x00, 0x00, 0x42, 0x68, 0x00, 0x01, 0x37, 0x54, 0x00, 0x00, 0x33, 0x2c,
+        0x00, 0x00, 0x11, 0xf8, 0x10, 0xb0, 0xf8, 0xa4,
+        // gAMA
+        0x00, 0x00, 0x00, 0x04, 0x67, 0x41, 0x4d, 0x41, 0x00, 0x01, 0x86, 0xa0,
+        0x31, 0xe8, 0x96, 0x5f,
+        // IDAT
+        0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x60, 0x60, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0xf6, 0x17, 0x38, 0x55,
+        // IEND
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    // The header of an EXR image, with the given chromaticities attribute
+    fn exr(chromaticities: &[f32; 8]) -> Vec<u8> {
+        let mut data = EXR_MAGIC.to_vec();
+        data.extend_from_slice(&[2, 0, 0, 0]);
+        let mut attribute = |name: &[u8], attribute_type: &[u8], value: &[u8]| {
+            data.extend_from_slice(name);
+            data.push(0);
+            data.extend_from_slice(attribute_type);
+            data.push(0);
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value);
+        };
+        attribute(b"compression", b"compression", &[0]);
+        let value: Vec<u8> = chromaticities
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        attribute(b"chromaticities", b"chromaticities", &value);
+        attribute(b"lineOrder", b"lineOrder", &[0]);
+        data.push(0);
+        data
+    }
+
+    const P3_D65: [f32; 8] = [0.680, 0.320, 0.265, 0.690, 0.150, 0.060, 0.3127, 0.3290];
+
+    #[test]
+    fn png_cicp_and_hdr_chunks() {
+        let metadata = parse_png_data(CICP_PNG).unwrap().unwrap();
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Pq));
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Rgb));
+        assert_eq!(basic.range, Some(ColorRange::Full));
+        let hdr = metadata.hdr.unwrap();
+        let coords = hdr.color_coords.unwrap();
+        let units = |(x, y): (f64, f64)| (chromaticity_to_units(x), chromaticity_to_units(y));
+        assert_eq!(units(coords.green), (8500, 39850));
+        assert_eq!(units(coords.blue), (6550, 2300));
+        assert_eq!(units(coords.red), (35400, 14600));
+        assert_eq!(units(coords.white), (15635, 16450));
+        assert_eq!(hdr.max_luma, 1000.);
+        assert_eq!(hdr.min_luma, 0.005);
+        assert_eq!(hdr.max_content_light, 1000);
+        assert_eq!(hdr.max_frame_light, 400);
+    }
+
+    #[test]
+    fn png_chromaticities_and_gamma() {
+        let metadata = parse_png_data(CHRM_PNG).unwrap().unwrap();
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Bt2020));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Linear));
+        assert_eq!(basic.matrix, Some(MatrixCoefficients::Rgb));
+        assert_eq!(basic.range, Some(ColorRange::Full));
+        assert!(metadata.hdr.is_none());
+    }
+
+    #[test]
+    fn exr_chromaticities() {
+        let metadata = parse_exr_data(&exr(&P3_D65)).unwrap().unwrap();
+        let basic = metadata.basic.unwrap();
+        assert_eq!(basic.primaries, Some(ColorPrimaries::Smpte432));
+        assert_eq!(basic.transfer, Some(TransferCharacteristics::Linear));
+    }
+
+    // Where the data of the given chunk starts
+    fn chunk_data(png: &[u8], chunk_type: &[u8]) -> usize {
+        png.windows(4)
+            .position(|bytes| bytes == chunk_type)
+            .unwrap()
+            + 4
+    }
+
+    #[test]
+    fn truncated_colour_chunks() {
+        for (png, chunk_type) in [
+            (CICP_PNG, "cICP"),
+            (CICP_PNG, "mDCV"),
+            (CICP_PNG, "cLLI"),
+            (CHRM_PNG, "cHRM"),
+            (CHRM_PNG, "gAMA"),
+        ] {
+            let len = chunk_data(png, chunk_type.as_bytes()) + 2;
+            let err = parse_png_data(&png[..len]).err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                format!("PNG ends partway through its {} chunk", chunk_type)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_images() {
+        assert!(parse_png_data(&[]).unwrap().is_none());
+        // Cut off after the colour chunks, partway through the pixels
+        let len = chunk_data(CICP_PNG, b"IDAT") + 2;
+        assert!(parse_png_data(&CICP_PNG[..len]).unwrap().is_some());
+        let exr = exr(&P3_D65);
+        for len in 4..exr.len() {
+            let err = parse_exr_data(&exr[..len]).err().unwrap();
+            assert_eq!(err.to_string(), "EXR header is truncated", "{}", len);
+        }
+    }
+}
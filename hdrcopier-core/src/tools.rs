@@ -0,0 +1,183 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::Result;
+use wait_timeout::ChildExt;
+
+// How long tools which read metadata may run before we give up on them, in seconds.
+// Corrupt or network-mounted files can otherwise make them hang forever.
+// Zero means no limit.
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(30);
+
+pub fn set_tool_timeout(timeout: Duration) {
+    TIMEOUT_SECS.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+// Hides the output of the tools which write files, for `--quiet`, and for when
+// stdout is being used for something else, e.g. `copy --json`
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn log_command(command: &Command) {
+    log::info!("Running: {:?}", command);
+}
+
+// Messages logged with this target are a status line, which only makes sense
+// while it's still on screen, so loggers may overwrite it with the next one.
+// An empty message means that whatever it described has finished.
+pub const STATUS_TARGET: &str = "hdrcopier::status";
+
+// The tools which read metadata are run in parallel,
+// so the status is only cleared once all of them are done
+static PROBES_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+pub fn start_probe(name: &str) {
+    PROBES_RUNNING.fetch_add(1, Ordering::Relaxed);
+    log::info!(target: STATUS_TARGET, "Probing with {}...", name);
+}
+
+pub fn finish_probe() {
+    if PROBES_RUNNING.fetch_sub(1, Ordering::Relaxed) == 1 {
+        log::info!(target: STATUS_TARGET, "");
+    }
+}
+
+// Creates a command which runs the given external tool. The location of each tool
+// can be overridden with an environment variable, e.g. `HDRCOPIER_MEDIAINFO` for
+// mediainfo, for when it isn't in PATH.
+pub fn tool_command(name: &str) -> Command {
+    Command::new(env::var_os(env_var(name)).unwrap_or_else(|| OsString::from(name)))
+}
+
+fn env_var(name: &str) -> String {
+    format!("HDRCOPIER_{}", name.to_uppercase())
+}
+
+// Running a tool which isn't installed otherwise fails with a generic
+// "No such file or directory", which doesn't say which tool is missing.
+pub trait ToolCommand {
+    fn tool_output(&mut self) -> Result<Output>;
+    fn tool_status(&mut self) -> Result<ExitStatus>;
+    fn tool_spawn(&mut self) -> Result<Child>;
+    // Like `tool_status`, but when quiet, the tool's output is
+    // only shown (on stderr) if it fails
+    fn tool_run(&mut self) -> Result<ExitStatus>;
+    // Like `tool_run`, but the tool's output is also returned so that it can be
+    // inspected. It's shown once the tool exits, rather than as it runs.
+    fn tool_run_captured(&mut self) -> Result<Output>;
+    // Like `tool_output`, but kills the tool if it runs for longer than the
+    // timeout. This is only used for reading, since killing a tool partway
+    // through writing could leave a broken file.
+    fn tool_output_with_timeout(&mut self) -> Result<Output>;
+}
+
+impl ToolCommand for Command {
+    fn tool_output(&mut self) -> Result<Output> {
+        self.output().map_err(|e| tool_error(self, e))
+    }
+
+    fn tool_status(&mut self) -> Result<ExitStatus> {
+        self.status().map_err(|e| tool_error(self, e))
+    }
+
+    fn tool_spawn(&mut self) -> Result<Child> {
+        self.spawn().map_err(|e| tool_error(self, e))
+    }
+
+    fn tool_run(&mut self) -> Result<ExitStatus> {
+        if !QUIET.load(Ordering::Relaxed) {
+            return self.tool_status();
+        }
+        let output = self.tool_output()?;
+        if !output.status.success() {
+            let mut stderr = io::stderr();
+            let _ = stderr.write_all(&output.stdout);
+            let _ = stderr.write_all(&output.stderr);
+        }
+        Ok(output.status)
+    }
+
+    fn tool_run_captured(&mut self) -> Result<Output> {
+        let output = self.tool_output()?;
+        if !QUIET.load(Ordering::Relaxed) {
+            let _ = io::stdout().write_all(&output.stdout);
+            let _ = io::stderr().write_all(&output.stderr);
+        } else if !output.status.success() {
+            let mut stderr = io::stderr();
+            let _ = stderr.write_all(&output.stdout);
+            let _ = stderr.write_all(&output.stderr);
+        }
+        Ok(output)
+    }
+
+    fn tool_output_with_timeout(&mut self) -> Result<Output> {
+        let timeout = TIMEOUT_SECS.load(Ordering::Relaxed);
+        if timeout == 0 {
+            return self.tool_output();
+        }
+
+        let mut child = self
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .tool_spawn()?;
+        // The pipes are read on other threads, so that a tool
+        // with lots of output can't fill them up and block
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+        let status = match child.wait_timeout(Duration::from_secs(timeout))? {
+            Some(status) => status,
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!(
+                    "{} timed out after {} seconds",
+                    self.get_program().to_string_lossy(),
+                    timeout
+                );
+            }
+        };
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+fn tool_error(command: &Command, error: io::Error) -> anyhow::Error {
+    let program = command.get_program().to_string_lossy();
+    if error.kind() != io::ErrorKind::NotFound {
+        return anyhow::anyhow!("Failed to run {}: {}", program, error);
+    }
+    let name = Path::new(command.get_program())
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or(program.clone());
+    anyhow::anyhow!(
+        "{} not found in PATH; install it or set {} to its location",
+        program,
+        env_var(&name)
+    )
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+
+use crate::{
+    metadata::Metadata,
+    values::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics},
+};
+
+// A combination of values which is technically allowed,
+// but which is almost certainly a tagging mistake
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationWarning {
+    // PQ or HLG transfer, but no mastering display to go with it
+    HdrTransferWithoutMasteringDisplay {
+        transfer: TransferCharacteristics,
+    },
+    // Mastering display data on content with an SDR transfer
+    MasteringDisplayWithSdrTransfer {
+        transfer: TransferCharacteristics,
+    },
+    // BT.2020 primaries with a BT.709 matrix, or the other way around
+    MismatchedPrimariesAndMatrix {
+        primaries: ColorPrimaries,
+        matrix: MatrixCoefficients,
+    },
+    // The display's minimum luminance is not below its maximum
+    InvalidLuminanceRange {
+        min_luma: f64,
+        max_luma: f64,
+    },
+    // Both content light levels are zero, but there is mastering display data
+    MissingContentLightLevels,
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValidationWarning::HdrTransferWithoutMasteringDisplay { transfer } => write!(
+                f,
+                "Transfer characteristics are {} ({}), but there is no mastering display metadata",
+                transfer.as_u8(),
+                if transfer == TransferCharacteristics::Pq {
+                    "PQ"
+                } else {
+                    "HLG"
+                }
+            ),
+            ValidationWarning::MasteringDisplayWithSdrTransfer { transfer } => write!(
+                f,
+                "There is mastering display metadata, but transfer characteristics are {}, \
+                 which is not HDR",
+                transfer.as_u8()
+            ),
+            ValidationWarning::MismatchedPrimariesAndMatrix { primaries, matrix } => write!(
+                f,
+                "Color primaries ({}) and matrix coefficients ({}) are from different standards",
+                primaries.as_u8(),
+                matrix.as_u8()
+            ),
+            ValidationWarning::InvalidLuminanceRange { min_luma, max_luma } => write!(
+                f,
+                "Minimum luminance ({}) is not less than maximum luminance ({})",
+                min_luma, max_luma
+            ),
+            ValidationWarning::MissingContentLightLevels => write!(
+                f,
+                "There is mastering display metadata, but the content light levels are both zero"
+            ),
+        }
+    }
+}
+
+// Looks for combinations of values which will confuse players
+pub fn validate_metadata(metadata: &Metadata) -> Vec<ValidationWarning> {
+    let mut warnings = Vec::new();
+    let coords = metadata
+        .hdr
+        .as_ref()
+        .and_then(|hdr| hdr.color_coords.as_ref());
+
+    if let Some(ref basic) = metadata.basic {
+        if let Some(transfer) = basic.transfer {
+            let hdr_transfer = matches!(
+                transfer,
+                TransferCharacteristics::Pq | TransferCharacteristics::Hlg
+            );
+            if hdr_transfer && coords.is_none() {
+                warnings.push(ValidationWarning::HdrTransferWithoutMasteringDisplay { transfer });
+            }
+            // An unspecified or unknown transfer may well be HDR, so only a known SDR one is flagged
+            let sdr_transfer = !hdr_transfer
+                && !matches!(
+                    transfer,
+                    TransferCharacteristics::Unspecified | TransferCharacteristics::Other(_)
+                );
+            if sdr_transfer && coords.is_some() {
+                warnings.push(ValidationWarning::MasteringDisplayWithSdrTransfer { transfer });
+            }
+        }
+
+        if let (Some(primaries), Some(matrix)) = (basic.primaries, basic.matrix) {
+            let bt2020_matrix = matches!(
+                matrix,
+                MatrixCoefficients::Bt2020Ncl | MatrixCoefficients::Bt2020Cl
+            );
+            if (primaries == ColorPrimaries::Bt2020 && matrix == MatrixCoefficients::Bt709)
+                || (primaries == ColorPrimaries::Bt709 && bt2020_matrix)
+            {
+                warnings
+                    .push(ValidationWarning::MismatchedPrimariesAndMatrix { primaries, matrix });
+            }
+        }
+    }
+
+    if let Some(ref hdr) = metadata.hdr {
+        if coords.is_some() && hdr.min_luma >= hdr.max_luma {
+            warnings.push(ValidationWarning::InvalidLuminanceRange {
+                min_luma: hdr.min_luma,
+                max_luma: hdr.max_luma,
+            });
+        }
+        if coords.is_some() && hdr.max_content_light == 0 && hdr.max_frame_light == 0 {
+            warnings.push(ValidationWarning::MissingContentLightLevels);
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{BasicMetadata, ColorCoordinates, HdrMetadata};
+
+    fn metadata(transfer: Option<TransferCharacteristics>, hdr: Option<HdrMetadata>) -> Metadata {
+        Metadata {
+            basic: Some(BasicMetadata {
+                matrix: Some(MatrixCoefficients::Bt2020Ncl),
+                primaries: Some(ColorPrimaries::Bt2020),
+                transfer,
+                ..Default::default()
+            }),
+            hdr,
+            ..Default::default()
+        }
+    }
+
+    fn mastering_display(min_luma: f64, max_luma: f64, content_light: u32) -> HdrMetadata {
+        HdrMetadata {
+            color_coords: Some(ColorCoordinates {
+                red: (0.708, 0.292),
+                green: (0.170, 0.797),
+                blue: (0.131, 0.046),
+                white: (0.3127, 0.329),
+            }),
+            max_luma,
+            min_luma,
+            max_content_light: content_light,
+            max_frame_light: content_light,
+        }
+    }
+
+    #[test]
+    fn consistent_hdr10() {
+        let metadata = metadata(
+            Some(TransferCharacteristics::Pq),
+            Some(mastering_display(0.0001, 1000., 400)),
+        );
+        assert_eq!(validate_metadata(&metadata), Vec::new());
+    }
+
+    #[test]
+    fn hdr_transfer_without_mastering_display() {
+        for transfer in [TransferCharacteristics::Pq, TransferCharacteristics::Hlg] {
+            assert_eq!(
+                validate_metadata(&metadata(Some(transfer), None)),
+                vec![ValidationWarning::HdrTransferWithoutMasteringDisplay { transfer }]
+            );
+        }
+    }
+
+    #[test]
+    fn mastering_display_with_sdr_transfer() {
+        let transfer = TransferCharacteristics::Bt709;
+        assert_eq!(
+            validate_metadata(&metadata(
+                Some(transfer),
+                Some(mastering_display(0.0001, 1000., 400))
+            )),
+            vec![ValidationWarning::MasteringDisplayWithSdrTransfer { transfer }]
+        );
+        // Nothing says these aren't HDR
+        for transfer in [
+            None,
+            Some(TransferCharacteristics::Unspecified),
+            Some(TransferCharacteristics::Other(3)),
+        ] {
+            assert_eq!(
+                validate_metadata(&metadata(
+                    transfer,
+                    Some(mastering_display(0.0001, 1000., 400))
+                )),
+                Vec::new()
+            );
+        }
+    }
+
+    #[test]
+    fn mismatched_primaries_and_matrix() {
+        let mut metadata = metadata(None, None);
+        let basic = metadata.basic.as_mut().unwrap();
+        basic.matrix = Some(MatrixCoefficients::Bt709);
+        assert_eq!(
+            validate_metadata(&metadata),
+            vec![ValidationWarning::MismatchedPrimariesAndMatrix {
+                primaries: ColorPrimaries::Bt2020,
+                matrix: MatrixCoefficients::Bt709,
+            }]
+        );
+        let basic = metadata.basic.as_mut().unwrap();
+        basic.primaries = Some(ColorPrimaries::Bt709);
+        basic.matrix = Some(MatrixCoefficients::Bt2020Cl);
+        assert_eq!(
+            validate_metadata(&metadata),
+            vec![ValidationWarning::MismatchedPrimariesAndMatrix {
+                primaries: ColorPrimaries::Bt709,
+                matrix: MatrixCoefficients::Bt2020Cl,
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_luminance_range() {
+        assert_eq!(
+            validate_metadata(&metadata(
+                Some(TransferCharacteristics::Pq),
+                Some(mastering_display(1000., 1000., 400))
+            )),
+            vec![ValidationWarning::InvalidLuminanceRange {
+                min_luma: 1000.,
+                max_luma: 1000.,
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_content_light_levels() {
+        assert_eq!(
+            validate_metadata(&metadata(
+                Some(TransferCharacteristics::Pq),
+                Some(mastering_display(0.0001, 1000., 0))
+            )),
+            vec![ValidationWarning::MissingContentLightLevels]
+        );
+    }
+}
@@ -1,357 +1,1069 @@
-pub fn parse_color_range(value: &str) -> u8 {
-    match value.to_lowercase().as_str() {
-        "limited" => 1,
-        "full" => 0,
-        _ => panic!("Unrecognized color range"),
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+// Returned when a value for one of the color fields isn't one we know about,
+// or can't be expressed in the requested output format.
+#[derive(Debug)]
+pub struct UnknownValueError {
+    field: &'static str,
+    value: String,
+    encoder: Option<&'static str>,
+}
+
+impl UnknownValueError {
+    fn new<T: ToString>(field: &'static str, value: T) -> Self {
+        UnknownValueError {
+            field,
+            value: value.to_string(),
+            encoder: None,
+        }
+    }
+
+    fn unsupported<T: ToString>(field: &'static str, value: T, encoder: &'static str) -> Self {
+        UnknownValueError {
+            field,
+            value: value.to_string(),
+            encoder: Some(encoder),
+        }
+    }
+
+    // Whether the value is valid, but has no name in the requested output format
+    pub(crate) fn is_unsupported(&self) -> bool {
+        self.encoder.is_some()
     }
 }
 
-pub fn print_color_range(value: u8) -> &'static str {
-    match value {
-        0 => "Full",
-        1 => "Limited",
-        _ => panic!("Unrecognized color range"),
+impl fmt::Display for UnknownValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.encoder {
+            Some(encoder) => write!(
+                f,
+                "Value {} for {} is not supported by {}",
+                self.value, self.field, encoder
+            ),
+            None => write!(f, "Unrecognized {}: {}", self.field, self.value),
+        }
     }
 }
 
-pub fn color_range_to_mkvedit_prop(value: u8) -> u8 {
+impl Error for UnknownValueError {}
+
+// The color range, which hdrcopier has always stored as 0 for full and 1 for limited.
+// This is its own convention, not a standard one, so Matroska's and AV1's codes
+// go through `from_mkvedit_prop`/`to_mkvedit_prop` and `to_av1` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum ColorRange {
+    Full,
+    Limited,
+    // Anything else is kept as-is, so that it can be shown and copied
+    Other(u8),
+}
+
+impl ColorRange {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ColorRange::Full,
+            1 => ColorRange::Limited,
+            _ => ColorRange::Other(value),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            ColorRange::Full => 0,
+            ColorRange::Limited => 1,
+            ColorRange::Other(value) => value,
+        }
+    }
+
+    fn unknown(self) -> UnknownValueError {
+        UnknownValueError::new("color range", self.as_u8())
+    }
+
+    // Accepts the names used by mediainfo, e.g. `Limited` or `Limited range`,
+    // as well as ffmpeg's `tv`/`pc` and their `mpeg`/`jpeg` aliases
+    pub fn from_mediainfo(value: &str) -> Result<Self, UnknownValueError> {
+        let lowercase = value.to_lowercase();
+        match lowercase.strip_suffix(" range").unwrap_or(&lowercase) {
+            "limited" | "tv" | "mpeg" => Ok(ColorRange::Limited),
+            "full" | "pc" | "jpeg" => Ok(ColorRange::Full),
+            _ => Err(UnknownValueError::new("color range", value)),
+        }
+    }
+
+    pub fn from_ffmpeg(value: &str) -> Result<Self, UnknownValueError> {
+        match value {
+            "pc" | "jpeg" => Ok(ColorRange::Full),
+            "tv" | "mpeg" => Ok(ColorRange::Limited),
+            _ => Err(UnknownValueError::new("color range", value)),
+        }
+    }
+
     // mkvpropedit uses "2" for "Full" instead of "0" which is what everyone else uses.
     // "0" to them means "unset"
-    if value == 0 {
-        return 2;
+    pub fn to_mkvedit_prop(self) -> u8 {
+        match self {
+            ColorRange::Full => 2,
+            ColorRange::Limited => 1,
+            ColorRange::Other(value) => value,
+        }
     }
-    value
-}
 
-pub fn print_x265_color_range(value: u8) -> &'static str {
-    match value {
-        0 => "full",
-        1 => "limited",
-        _ => panic!("Unrecognized color range"),
+    // The inverse of `to_mkvedit_prop`, for values read from Matroska headers, which
+    // number the ranges differently from us. Returns `None` for "unset" and "defined by
+    // matrix coefficients and transfer", which give no range to copy.
+    pub fn from_mkvedit_prop(value: u8) -> Option<Self> {
+        match value {
+            0 | 3 => None,
+            1 => Some(ColorRange::Limited),
+            2 => Some(ColorRange::Full),
+            // Not a value Matroska defines, so it's kept to be written back as-is
+            _ => Some(ColorRange::Other(value)),
+        }
+    }
+
+    // The AV1 spec, and so SvtAv1EncApp and aomenc, use "1" for "Full" and "0" for "Studio",
+    // the opposite of everyone else
+    pub fn to_av1(self) -> Result<u8, UnknownValueError> {
+        match self {
+            ColorRange::Full => Ok(1),
+            ColorRange::Limited => Ok(0),
+            ColorRange::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorRange::Full => Ok("Full"),
+            ColorRange::Limited => Ok("Limited"),
+            ColorRange::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn x265_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorRange::Full => Ok("full"),
+            ColorRange::Limited => Ok("limited"),
+            ColorRange::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn x264_name(self) -> Result<&'static str, UnknownValueError> {
+        self.ffmpeg_name()
+    }
+
+    pub fn rav1e_name(self) -> Result<&'static str, UnknownValueError> {
+        self.name()
+    }
+
+    pub fn ffmpeg_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorRange::Full => Ok("pc"),
+            ColorRange::Limited => Ok("tv"),
+            ColorRange::Other(_) => Err(self.unknown()),
+        }
     }
-}
 
-pub fn print_rav1e_color_range(value: u8) -> &'static str {
-    match value {
-        0 => "Full",
-        1 => "Limited",
-        _ => panic!("Unrecognized color range"),
+    pub fn zscale_name(self) -> Result<&'static str, UnknownValueError> {
+        self.x265_name()
     }
 }
 
-pub fn print_svtav1_color_range(value: u8) -> &'static str {
-    match value {
-        0 => "full",
-        1 => "studio",
-        _ => panic!("Unrecognized color range"),
+impl From<u8> for ColorRange {
+    fn from(value: u8) -> Self {
+        ColorRange::from_u8(value)
     }
 }
 
-pub fn parse_matrix_coefficients(value: &str) -> u8 {
-    match value.to_lowercase().as_str() {
-        "rgb" => 0,
-        "bt.709" => 1,
-        "unspecified" | "unset" => 2,
-        "fcc" => 4,
-        "bt.470 bg" => 5,
-        "smpte 170m" | "bt.601" => 6,
-        "smpte 240m" => 7,
-        "ycgco" => 8,
-        "bt.2020 non-constant" => 9,
-        "bt.2020 constant" => 10,
-        // FIXME: Not sure how these two are formatted in mediainfo
-        // VSC_MATRIX_CHROMATICITY_DERIVED_NCL = 12,
-        // VSC_MATRIX_CHROMATICITY_DERIVED_CL = 13,
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+impl From<ColorRange> for u8 {
+    fn from(value: ColorRange) -> Self {
+        value.as_u8()
     }
 }
 
-pub fn print_matrix_coefficients(value: u8) -> &'static str {
-    match value {
-        0 => "RGB",
-        1 => "BT.709",
-        2 => "Unspecified",
-        4 => "FCC",
-        5 => "BT.470 BG",
-        6 => "SMPTE 170m/BT.601",
-        7 => "SMPTE 240m",
-        8 => "YCgCo",
-        9 => "BT.2020 Non-Constant Light",
-        10 => "BT.2020 Constant Light",
-        12 => "Chroma-Derived Non-Constant Light",
-        13 => "Chroma-Derived Constant Light",
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+// Shows the name, or the raw code for values we don't recognize
+impl fmt::Display for ColorRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "Unknown ({})", self.as_u8()),
+        }
     }
 }
 
-pub fn print_x265_matrix_coefficients(value: u8) -> &'static str {
-    match value {
-        0 => panic!("RGB not supported by x265"),
-        1 => "bt709",
-        2 => "unknown",
-        4 => "fcc",
-        5 => "bt470bg",
-        6 => "smpte170m",
-        7 => "smpte240m",
-        8 => "ycgco",
-        9 => "bt2020nc",
-        10 => "bt2020c",
-        12 => "chroma-derived-nc",
-        13 => "chroma-derived-c",
-        // FIXME: The following are x265 options with an unknown number value
-        // gbr
-        // smpte2085
-        // ictcp
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+// The color primaries, with the codes from ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum ColorPrimaries {
+    Bt709,
+    Unspecified,
+    Bt470M,
+    Bt470Bg,
+    Smpte170M,
+    Smpte240M,
+    Film,
+    Bt2020,
+    Smpte428,
+    Smpte431,
+    Smpte432,
+    Ebu3213,
+    // Anything else is kept as-is, so that it can be shown and copied
+    Other(u8),
+}
+
+impl ColorPrimaries {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ColorPrimaries::Bt709,
+            2 => ColorPrimaries::Unspecified,
+            4 => ColorPrimaries::Bt470M,
+            5 => ColorPrimaries::Bt470Bg,
+            6 => ColorPrimaries::Smpte170M,
+            7 => ColorPrimaries::Smpte240M,
+            8 => ColorPrimaries::Film,
+            9 => ColorPrimaries::Bt2020,
+            10 => ColorPrimaries::Smpte428,
+            11 => ColorPrimaries::Smpte431,
+            12 => ColorPrimaries::Smpte432,
+            22 => ColorPrimaries::Ebu3213,
+            _ => ColorPrimaries::Other(value),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            ColorPrimaries::Bt709 => 1,
+            ColorPrimaries::Unspecified => 2,
+            ColorPrimaries::Bt470M => 4,
+            ColorPrimaries::Bt470Bg => 5,
+            ColorPrimaries::Smpte170M => 6,
+            ColorPrimaries::Smpte240M => 7,
+            ColorPrimaries::Film => 8,
+            ColorPrimaries::Bt2020 => 9,
+            ColorPrimaries::Smpte428 => 10,
+            ColorPrimaries::Smpte431 => 11,
+            ColorPrimaries::Smpte432 => 12,
+            ColorPrimaries::Ebu3213 => 22,
+            ColorPrimaries::Other(value) => value,
+        }
+    }
+
+    fn unknown(self) -> UnknownValueError {
+        UnknownValueError::new("color primaries", self.as_u8())
+    }
+
+    fn unsupported(self, encoder: &'static str) -> UnknownValueError {
+        UnknownValueError::unsupported("color primaries", self.as_u8(), encoder)
+    }
+
+    pub fn from_mediainfo(value: &str) -> Result<Self, UnknownValueError> {
+        match value.to_lowercase().as_str() {
+            "bt.709" => Ok(ColorPrimaries::Bt709),
+            "unspecified" | "unset" => Ok(ColorPrimaries::Unspecified),
+            "bt.470 m" => Ok(ColorPrimaries::Bt470M),
+            "bt.470 bg" => Ok(ColorPrimaries::Bt470Bg),
+            "smpte 170m" | "bt.601" => Ok(ColorPrimaries::Smpte170M),
+            "smpte 240m" => Ok(ColorPrimaries::Smpte240M),
+            "film" | "ntsc" => Ok(ColorPrimaries::Film),
+            "bt.2020" => Ok(ColorPrimaries::Bt2020),
+            "smpte 428" => Ok(ColorPrimaries::Smpte428),
+            "smpte 431.2" => Ok(ColorPrimaries::Smpte431),
+            "smpte 432.1" => Ok(ColorPrimaries::Smpte432),
+            "ebu 3213 e" => Ok(ColorPrimaries::Ebu3213),
+            _ => Err(UnknownValueError::new("color primaries", value)),
+        }
+    }
+
+    pub fn from_ffmpeg(value: &str) -> Result<Self, UnknownValueError> {
+        match value {
+            "bt709" => Ok(ColorPrimaries::Bt709),
+            "unknown" => Ok(ColorPrimaries::Unspecified),
+            "bt470m" => Ok(ColorPrimaries::Bt470M),
+            "bt470bg" => Ok(ColorPrimaries::Bt470Bg),
+            "smpte170m" => Ok(ColorPrimaries::Smpte170M),
+            "smpte240m" => Ok(ColorPrimaries::Smpte240M),
+            "film" => Ok(ColorPrimaries::Film),
+            "bt2020" => Ok(ColorPrimaries::Bt2020),
+            "smpte428" => Ok(ColorPrimaries::Smpte428),
+            "smpte431" => Ok(ColorPrimaries::Smpte431),
+            "smpte432" => Ok(ColorPrimaries::Smpte432),
+            "ebu3213" => Ok(ColorPrimaries::Ebu3213),
+            _ => Err(UnknownValueError::new("color primaries", value)),
+        }
+    }
+
+    pub fn name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Bt709 => Ok("BT.709"),
+            ColorPrimaries::Unspecified => Ok("Unspecified"),
+            ColorPrimaries::Bt470M => Ok("BT.470 M"),
+            ColorPrimaries::Bt470Bg => Ok("BT.470 BG"),
+            ColorPrimaries::Smpte170M => Ok("SMPTE 170m/BT.601"),
+            ColorPrimaries::Smpte240M => Ok("SMPTE 240m"),
+            ColorPrimaries::Film => Ok("Film"),
+            ColorPrimaries::Bt2020 => Ok("BT.2020"),
+            ColorPrimaries::Smpte428 => Ok("SMPTE 428"),
+            ColorPrimaries::Smpte431 => Ok("SMPTE 431.2"),
+            ColorPrimaries::Smpte432 => Ok("SMPTE 432.1"),
+            ColorPrimaries::Ebu3213 => Ok("EBU 3213 E"),
+            ColorPrimaries::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn x265_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Unspecified => Ok("unknown"),
+            ColorPrimaries::Ebu3213 => Err(self.unsupported("x265")),
+            _ => self.ffmpeg_name(),
+        }
+    }
+
+    // x264 shares most names with x265, but calls unspecified values "undef"
+    pub fn x264_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Unspecified => Ok("undef"),
+            ColorPrimaries::Ebu3213 => Err(self.unsupported("x264")),
+            _ => self.x265_name(),
+        }
+    }
+
+    pub fn rav1e_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Bt709 => Ok("BT709"),
+            ColorPrimaries::Unspecified => Ok("Unspecified"),
+            ColorPrimaries::Bt470M => Ok("BT470M"),
+            ColorPrimaries::Bt470Bg => Ok("BT470BG"),
+            ColorPrimaries::Smpte170M => Ok("BT601"),
+            ColorPrimaries::Smpte240M => Ok("SMPTE240"),
+            ColorPrimaries::Film => Ok("GenericFilm"),
+            ColorPrimaries::Bt2020 => Ok("BT2020"),
+            ColorPrimaries::Smpte428 => Ok("XYZ"),
+            ColorPrimaries::Smpte431 => Ok("SMPTE431"),
+            ColorPrimaries::Smpte432 => Ok("SMPTE432"),
+            ColorPrimaries::Ebu3213 => Ok("EBU3213"),
+            ColorPrimaries::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn aom_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Bt709 => Ok("bt709"),
+            ColorPrimaries::Unspecified => Ok("unspecified"),
+            ColorPrimaries::Bt470M => Ok("bt470m"),
+            ColorPrimaries::Bt470Bg => Ok("bt470bg"),
+            ColorPrimaries::Smpte170M => Ok("bt601"),
+            ColorPrimaries::Smpte240M => Ok("smpte240"),
+            ColorPrimaries::Film => Ok("film"),
+            ColorPrimaries::Bt2020 => Ok("bt2020"),
+            ColorPrimaries::Smpte428 => Ok("xyz"),
+            ColorPrimaries::Smpte431 => Ok("smpte431"),
+            ColorPrimaries::Smpte432 => Ok("smpte432"),
+            ColorPrimaries::Ebu3213 => Ok("ebu3213"),
+            ColorPrimaries::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn ffmpeg_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Bt709 => Ok("bt709"),
+            ColorPrimaries::Unspecified => Ok("unknown"),
+            ColorPrimaries::Bt470M => Ok("bt470m"),
+            ColorPrimaries::Bt470Bg => Ok("bt470bg"),
+            ColorPrimaries::Smpte170M => Ok("smpte170m"),
+            ColorPrimaries::Smpte240M => Ok("smpte240m"),
+            ColorPrimaries::Film => Ok("film"),
+            ColorPrimaries::Bt2020 => Ok("bt2020"),
+            ColorPrimaries::Smpte428 => Ok("smpte428"),
+            ColorPrimaries::Smpte431 => Ok("smpte431"),
+            ColorPrimaries::Smpte432 => Ok("smpte432"),
+            ColorPrimaries::Ebu3213 => Ok("ebu3213"),
+            ColorPrimaries::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    // zscale's own names where it has them, and otherwise ffmpeg's names,
+    // which it also accepts for values that zimg added later
+    pub fn zscale_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            ColorPrimaries::Bt709 => Ok("709"),
+            ColorPrimaries::Unspecified => Ok("unspecified"),
+            ColorPrimaries::Bt470M => Ok("bt470m"),
+            ColorPrimaries::Bt470Bg => Ok("bt470bg"),
+            ColorPrimaries::Smpte170M => Ok("170m"),
+            ColorPrimaries::Smpte240M => Ok("240m"),
+            ColorPrimaries::Film => Ok("film"),
+            ColorPrimaries::Bt2020 => Ok("2020"),
+            ColorPrimaries::Smpte428 => Ok("smpte428"),
+            ColorPrimaries::Smpte431 => Ok("smpte431"),
+            ColorPrimaries::Smpte432 => Ok("smpte432"),
+            ColorPrimaries::Ebu3213 => Ok("ebu3213"),
+            ColorPrimaries::Other(_) => Err(self.unknown()),
+        }
     }
 }
 
-pub fn print_svtav1_matrix_coefficients(value: u8) -> &'static str {
-    match value {
-        0 => "identity",
-        1 => "bt709",
-        2 => "unspecified",
-        4 => "fcc",
-        5 => "bt470bg",
-        6 => "bt601",
-        7 => "smpte240",
-        8 => "ycgco",
-        9 => "bt2020-ncl",
-        10 => "bt2020-cl",
-        11 => "smpte2085",
-        12 => "chroma-ncl",
-        13 => "chroma-cl",
-        14 => "ictcp",
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+impl From<u8> for ColorPrimaries {
+    fn from(value: u8) -> Self {
+        ColorPrimaries::from_u8(value)
     }
 }
 
-pub fn print_rav1e_matrix_coefficients(value: u8) -> &'static str {
-    match value {
-        0 => panic!("RGB not supported by rav1e"),
-        1 => "BT709",
-        2 => "Unspecified",
-        4 => "FCC",
-        5 => "BT470BG",
-        6 => "BT601",
-        7 => "SMPTE240",
-        8 => "YCgCo",
-        9 => "BT2020NCL",
-        10 => "BT2020CL",
-        12 => "ChromatNCL",
-        13 => "ChromatCL",
-        // FIXME: The following are rav1e options with an unknown number value
-        // Identity
-        // SMPTE2085
-        // ICtCp
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+impl From<ColorPrimaries> for u8 {
+    fn from(value: ColorPrimaries) -> Self {
+        value.as_u8()
     }
 }
 
-pub fn parse_transfer_characteristics(value: &str) -> u8 {
-    match value.to_lowercase().as_str() {
-        "bt.709" => 1,
-        "unspecified" | "unset" => 2,
-        "bt.470 m" => 4,
-        "bt.470 bg" => 5,
-        "bt.601" => 6,
-        "smpte 240m" => 7,
-        "linear" => 8,
-        "log 100" => 9,
-        "log 316" => 10,
-        "iec 61966-2-4" => 11,
-        "iec 61966-2-1" => 13,
-        "bt.2020 10-bit" => 14,
-        "bt.2020 12-bit" => 15,
-        "pq" | "smpte 2084" => 16,
-        "arib b67" => 18,
-        "hlg" => 19,
-        _ => panic!("Unrecognized transfer characteristics: {value}"),
+impl fmt::Display for ColorPrimaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "Unknown ({})", self.as_u8()),
+        }
     }
 }
 
-pub fn print_transfer_characteristics(value: u8) -> &'static str {
-    match value {
-        1 => "BT.709",
-        2 => "Unspecified",
-        4 => "BT.470 M",
-        5 => "BT.470 BG",
-        6 => "SMPTE 170m/BT.601",
-        7 => "SMPTE 240m",
-        8 => "Linear",
-        9 => "Log 100",
-        10 => "Log 316",
-        11 => "IEC 61966-2-4",
-        13 => "IEC 61966-2-1",
-        14 => "BT.2020 10-bit",
-        15 => "BT.2020 12-bit",
-        16 => "PQ/SMPTE 2084",
-        18 => "ARIB B67",
-        19 => "HLG",
-        _ => panic!("Unrecognized transfer characteristics: {value}"),
+// The transfer characteristics, with the codes from ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum TransferCharacteristics {
+    Bt709,
+    Unspecified,
+    Bt470M,
+    Bt470Bg,
+    Smpte170M,
+    Smpte240M,
+    Linear,
+    Log100,
+    Log316,
+    // IEC 61966-2-4
+    Xvycc,
+    Bt1361,
+    // IEC 61966-2-1
+    Srgb,
+    Bt2020TenBit,
+    Bt2020TwelveBit,
+    // SMPTE 2084
+    Pq,
+    Smpte428,
+    // ARIB STD-B67
+    Hlg,
+    // Anything else is kept as-is, so that it can be shown and copied
+    Other(u8),
+}
+
+impl TransferCharacteristics {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TransferCharacteristics::Bt709,
+            2 => TransferCharacteristics::Unspecified,
+            4 => TransferCharacteristics::Bt470M,
+            5 => TransferCharacteristics::Bt470Bg,
+            6 => TransferCharacteristics::Smpte170M,
+            7 => TransferCharacteristics::Smpte240M,
+            8 => TransferCharacteristics::Linear,
+            9 => TransferCharacteristics::Log100,
+            10 => TransferCharacteristics::Log316,
+            11 => TransferCharacteristics::Xvycc,
+            12 => TransferCharacteristics::Bt1361,
+            13 => TransferCharacteristics::Srgb,
+            14 => TransferCharacteristics::Bt2020TenBit,
+            15 => TransferCharacteristics::Bt2020TwelveBit,
+            16 => TransferCharacteristics::Pq,
+            17 => TransferCharacteristics::Smpte428,
+            18 => TransferCharacteristics::Hlg,
+            _ => TransferCharacteristics::Other(value),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            TransferCharacteristics::Bt709 => 1,
+            TransferCharacteristics::Unspecified => 2,
+            TransferCharacteristics::Bt470M => 4,
+            TransferCharacteristics::Bt470Bg => 5,
+            TransferCharacteristics::Smpte170M => 6,
+            TransferCharacteristics::Smpte240M => 7,
+            TransferCharacteristics::Linear => 8,
+            TransferCharacteristics::Log100 => 9,
+            TransferCharacteristics::Log316 => 10,
+            TransferCharacteristics::Xvycc => 11,
+            TransferCharacteristics::Bt1361 => 12,
+            TransferCharacteristics::Srgb => 13,
+            TransferCharacteristics::Bt2020TenBit => 14,
+            TransferCharacteristics::Bt2020TwelveBit => 15,
+            TransferCharacteristics::Pq => 16,
+            TransferCharacteristics::Smpte428 => 17,
+            TransferCharacteristics::Hlg => 18,
+            TransferCharacteristics::Other(value) => value,
+        }
+    }
+
+    fn unknown(self) -> UnknownValueError {
+        UnknownValueError::new("transfer characteristics", self.as_u8())
+    }
+
+    pub fn from_mediainfo(value: &str) -> Result<Self, UnknownValueError> {
+        match value.to_lowercase().as_str() {
+            "bt.709" => Ok(TransferCharacteristics::Bt709),
+            "unspecified" | "unset" => Ok(TransferCharacteristics::Unspecified),
+            "bt.470 m" => Ok(TransferCharacteristics::Bt470M),
+            "bt.470 bg" => Ok(TransferCharacteristics::Bt470Bg),
+            "bt.601" => Ok(TransferCharacteristics::Smpte170M),
+            "smpte 240m" => Ok(TransferCharacteristics::Smpte240M),
+            "linear" => Ok(TransferCharacteristics::Linear),
+            "log 100" => Ok(TransferCharacteristics::Log100),
+            "log 316" => Ok(TransferCharacteristics::Log316),
+            "iec 61966-2-4" => Ok(TransferCharacteristics::Xvycc),
+            "bt.1361" => Ok(TransferCharacteristics::Bt1361),
+            "iec 61966-2-1" => Ok(TransferCharacteristics::Srgb),
+            "bt.2020 10-bit" => Ok(TransferCharacteristics::Bt2020TenBit),
+            "bt.2020 12-bit" => Ok(TransferCharacteristics::Bt2020TwelveBit),
+            "pq" | "smpte 2084" => Ok(TransferCharacteristics::Pq),
+            "smpte 428" | "smpte 428m" => Ok(TransferCharacteristics::Smpte428),
+            "arib b67" | "hlg" | "bt.2100 hlg" | "hybrid log-gamma" => {
+                Ok(TransferCharacteristics::Hlg)
+            }
+            _ => Err(UnknownValueError::new("transfer characteristics", value)),
+        }
+    }
+
+    pub fn from_ffmpeg(value: &str) -> Result<Self, UnknownValueError> {
+        match value {
+            "bt709" => Ok(TransferCharacteristics::Bt709),
+            "unknown" => Ok(TransferCharacteristics::Unspecified),
+            "bt470m" => Ok(TransferCharacteristics::Bt470M),
+            "bt470bg" => Ok(TransferCharacteristics::Bt470Bg),
+            "smpte170m" => Ok(TransferCharacteristics::Smpte170M),
+            "smpte240m" => Ok(TransferCharacteristics::Smpte240M),
+            "linear" => Ok(TransferCharacteristics::Linear),
+            "log100" => Ok(TransferCharacteristics::Log100),
+            "log316" => Ok(TransferCharacteristics::Log316),
+            "iec61966-2-4" => Ok(TransferCharacteristics::Xvycc),
+            "bt1361e" => Ok(TransferCharacteristics::Bt1361),
+            "iec61966-2-1" => Ok(TransferCharacteristics::Srgb),
+            "bt2020-10" => Ok(TransferCharacteristics::Bt2020TenBit),
+            "bt2020-12" => Ok(TransferCharacteristics::Bt2020TwelveBit),
+            "smpte2084" => Ok(TransferCharacteristics::Pq),
+            "smpte428" => Ok(TransferCharacteristics::Smpte428),
+            "arib-std-b67" => Ok(TransferCharacteristics::Hlg),
+            _ => Err(UnknownValueError::new("transfer characteristics", value)),
+        }
+    }
+
+    pub fn name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Bt709 => Ok("BT.709"),
+            TransferCharacteristics::Unspecified => Ok("Unspecified"),
+            TransferCharacteristics::Bt470M => Ok("BT.470 M"),
+            TransferCharacteristics::Bt470Bg => Ok("BT.470 BG"),
+            TransferCharacteristics::Smpte170M => Ok("SMPTE 170m/BT.601"),
+            TransferCharacteristics::Smpte240M => Ok("SMPTE 240m"),
+            TransferCharacteristics::Linear => Ok("Linear"),
+            TransferCharacteristics::Log100 => Ok("Log 100"),
+            TransferCharacteristics::Log316 => Ok("Log 316"),
+            TransferCharacteristics::Xvycc => Ok("IEC 61966-2-4"),
+            TransferCharacteristics::Bt1361 => Ok("BT.1361"),
+            TransferCharacteristics::Srgb => Ok("IEC 61966-2-1"),
+            TransferCharacteristics::Bt2020TenBit => Ok("BT.2020 10-bit"),
+            TransferCharacteristics::Bt2020TwelveBit => Ok("BT.2020 12-bit"),
+            TransferCharacteristics::Pq => Ok("PQ/SMPTE 2084"),
+            TransferCharacteristics::Smpte428 => Ok("SMPTE 428"),
+            TransferCharacteristics::Hlg => Ok("HLG / ARIB B67"),
+            TransferCharacteristics::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    // x265 uses the same names as ffmpeg
+    pub fn x265_name(self) -> Result<&'static str, UnknownValueError> {
+        self.ffmpeg_name()
+    }
+
+    // x264 shares most names with x265, but calls unspecified values "undef"
+    pub fn x264_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Unspecified => Ok("undef"),
+            _ => self.x265_name(),
+        }
+    }
+
+    pub fn rav1e_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Bt709 => Ok("BT709"),
+            TransferCharacteristics::Unspecified => Ok("Unspecified"),
+            TransferCharacteristics::Bt470M => Ok("BT470M"),
+            TransferCharacteristics::Bt470Bg => Ok("BT470BG"),
+            TransferCharacteristics::Smpte170M => Ok("BT601"),
+            TransferCharacteristics::Smpte240M => Ok("SMPTE240"),
+            TransferCharacteristics::Linear => Ok("Linear"),
+            TransferCharacteristics::Log100 => Ok("Log100"),
+            TransferCharacteristics::Log316 => Ok("Log100Sqrt10"),
+            TransferCharacteristics::Xvycc => Ok("IEC61966"),
+            TransferCharacteristics::Bt1361 => Ok("BT1361"),
+            TransferCharacteristics::Srgb => Ok("SRGB"),
+            TransferCharacteristics::Bt2020TenBit => Ok("BT2020_10Bit"),
+            TransferCharacteristics::Bt2020TwelveBit => Ok("BT2020_12Bit"),
+            TransferCharacteristics::Pq => Ok("SMPTE2084"),
+            TransferCharacteristics::Smpte428 => Ok("SMPTE428"),
+            TransferCharacteristics::Hlg => Ok("HLG"),
+            TransferCharacteristics::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn aom_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Bt709 => Ok("bt709"),
+            TransferCharacteristics::Unspecified => Ok("unspecified"),
+            TransferCharacteristics::Bt470M => Ok("bt470m"),
+            TransferCharacteristics::Bt470Bg => Ok("bt470bg"),
+            TransferCharacteristics::Smpte170M => Ok("bt601"),
+            TransferCharacteristics::Smpte240M => Ok("smpte240"),
+            TransferCharacteristics::Linear => Ok("lin"),
+            TransferCharacteristics::Log100 => Ok("log100"),
+            TransferCharacteristics::Log316 => Ok("log100sq10"),
+            TransferCharacteristics::Xvycc => Ok("iec61966"),
+            TransferCharacteristics::Bt1361 => Ok("bt1361"),
+            TransferCharacteristics::Srgb => Ok("srgb"),
+            TransferCharacteristics::Bt2020TenBit => Ok("bt2020-10bit"),
+            TransferCharacteristics::Bt2020TwelveBit => Ok("bt2020-12bit"),
+            TransferCharacteristics::Pq => Ok("smpte2084"),
+            TransferCharacteristics::Smpte428 => Ok("smpte428"),
+            TransferCharacteristics::Hlg => Ok("hlg"),
+            TransferCharacteristics::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn ffmpeg_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Bt709 => Ok("bt709"),
+            TransferCharacteristics::Unspecified => Ok("unknown"),
+            TransferCharacteristics::Bt470M => Ok("bt470m"),
+            TransferCharacteristics::Bt470Bg => Ok("bt470bg"),
+            TransferCharacteristics::Smpte170M => Ok("smpte170m"),
+            TransferCharacteristics::Smpte240M => Ok("smpte240m"),
+            TransferCharacteristics::Linear => Ok("linear"),
+            TransferCharacteristics::Log100 => Ok("log100"),
+            TransferCharacteristics::Log316 => Ok("log316"),
+            TransferCharacteristics::Xvycc => Ok("iec61966-2-4"),
+            TransferCharacteristics::Bt1361 => Ok("bt1361e"),
+            TransferCharacteristics::Srgb => Ok("iec61966-2-1"),
+            TransferCharacteristics::Bt2020TenBit => Ok("bt2020-10"),
+            TransferCharacteristics::Bt2020TwelveBit => Ok("bt2020-12"),
+            TransferCharacteristics::Pq => Ok("smpte2084"),
+            TransferCharacteristics::Smpte428 => Ok("smpte428"),
+            TransferCharacteristics::Hlg => Ok("arib-std-b67"),
+            TransferCharacteristics::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    // zscale's own names where it has them, and otherwise ffmpeg's names.
+    // zimg has no IEC 61966-2-4, BT.1361 or SMPTE 428 transfer.
+    pub fn zscale_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            TransferCharacteristics::Bt709 => Ok("709"),
+            TransferCharacteristics::Unspecified => Ok("unspecified"),
+            TransferCharacteristics::Bt470M => Ok("bt470m"),
+            TransferCharacteristics::Bt470Bg => Ok("bt470bg"),
+            TransferCharacteristics::Smpte170M => Ok("601"),
+            TransferCharacteristics::Smpte240M => Ok("smpte240m"),
+            TransferCharacteristics::Linear => Ok("linear"),
+            TransferCharacteristics::Log100 => Ok("log100"),
+            TransferCharacteristics::Log316 => Ok("log316"),
+            TransferCharacteristics::Srgb => Ok("iec61966-2-1"),
+            TransferCharacteristics::Bt2020TenBit => Ok("2020_10"),
+            TransferCharacteristics::Bt2020TwelveBit => Ok("2020_12"),
+            TransferCharacteristics::Pq => Ok("smpte2084"),
+            TransferCharacteristics::Hlg => Ok("arib-std-b67"),
+            TransferCharacteristics::Xvycc
+            | TransferCharacteristics::Bt1361
+            | TransferCharacteristics::Smpte428
+            | TransferCharacteristics::Other(_) => Err(self.unknown()),
+        }
     }
 }
 
-pub fn print_x265_transfer_characteristics(value: u8) -> &'static str {
-    match value {
-        1 => "bt709",
-        2 => "unknown",
-        4 => "bt470m",
-        5 => "bt470bg",
-        6 => "smpte170m",
-        7 => "smpte240m",
-        8 => "linear",
-        9 => "log100",
-        10 => "log316",
-        11 => "iec61966-2-4",
-        13 => "iec61966-2-1",
-        14 => "bt2020-10",
-        15 => "bt2020-12",
-        16 => "smpte2084",
-        18 => "arib-std-b67",
-        // FIXME: The following are x265 options with an unknown number value
-        // bt1361e
-        // smpte428
-        _ => panic!("Unrecognized transfer characteristics: {value}"),
+impl From<u8> for TransferCharacteristics {
+    fn from(value: u8) -> Self {
+        TransferCharacteristics::from_u8(value)
     }
 }
 
-pub fn print_svtav1_transfer_characteristics(value: u8) -> &'static str {
-    match value {
-        1 => "bt709",
-        2 => "unspecified",
-        4 => "bt470m",
-        5 => "bt470bg",
-        6 => "bt601",
-        7 => "smpte240",
-        8 => "linear",
-        9 => "log100",
-        10 => "log100-sqrt10",
-        11 => "iec61966",
-        12 => "bt1361",
-        13 => "srgb",
-        14 => "bt2020-10",
-        15 => "bt2020-12",
-        16 => "smpte2084",
-        17 => "smpte428",
-        19 => "hlg",
-        _ => panic!("Unrecognized matrix coefficients: {value}"),
+impl From<TransferCharacteristics> for u8 {
+    fn from(value: TransferCharacteristics) -> Self {
+        value.as_u8()
     }
 }
 
-pub fn print_rav1e_transfer_characteristics(value: u8) -> &'static str {
-    match value {
-        1 => "BT709",
-        2 => "Unspecified",
-        4 => "BT470M",
-        5 => "BT470BG",
-        6 => "BT601",
-        7 => "SMPTE240",
-        8 => "Linear",
-        9 => "Log100",
-        10 => "Log100Sqrt10",
-        11 => "IEC61966",
-        13 => "SRGB",
-        14 => "BT2020_10Bit",
-        15 => "BT2020_12Bit",
-        16 => "SMPTE2084",
-        18 => panic!("ARIB B67 not supported by rav1e"),
-        // FIXME: The following are rav1e options with an unknown number value
-        // BT1361
-        // SMPTE428
-        // HLG
-        _ => panic!("Unrecognized transfer characteristics: {value}"),
+impl fmt::Display for TransferCharacteristics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "Unknown ({})", self.as_u8()),
+        }
     }
 }
 
-pub fn parse_color_primaries(value: &str) -> u8 {
-    match value.to_lowercase().as_str() {
-        "bt.709" => 1,
-        "unspecified" | "unset" => 2,
-        "bt.470 m" => 4,
-        "bt.470 bg" => 5,
-        "smpte 170m" | "bt.601" => 6,
-        "smpte 240m" => 7,
-        "film" | "ntsc" => 8,
-        "bt.2020" => 9,
-        "smpte 428" => 10,
-        "smpte 431.2" => 11,
-        "smpte 432.1" => 12,
-        "ebu 3213 e" => 22,
-        _ => panic!("Unrecognized color primaries: {value}"),
+// The matrix coefficients, with the codes from ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum MatrixCoefficients {
+    // Also known as identity
+    Rgb,
+    Bt709,
+    Unspecified,
+    Fcc,
+    Bt470Bg,
+    Smpte170M,
+    Smpte240M,
+    YCgCo,
+    Bt2020Ncl,
+    Bt2020Cl,
+    Smpte2085,
+    ChromaDerivedNcl,
+    ChromaDerivedCl,
+    ICtCp,
+    // Anything else is kept as-is, so that it can be shown and copied
+    Other(u8),
+}
+
+impl MatrixCoefficients {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => MatrixCoefficients::Rgb,
+            1 => MatrixCoefficients::Bt709,
+            2 => MatrixCoefficients::Unspecified,
+            4 => MatrixCoefficients::Fcc,
+            5 => MatrixCoefficients::Bt470Bg,
+            6 => MatrixCoefficients::Smpte170M,
+            7 => MatrixCoefficients::Smpte240M,
+            8 => MatrixCoefficients::YCgCo,
+            9 => MatrixCoefficients::Bt2020Ncl,
+            10 => MatrixCoefficients::Bt2020Cl,
+            11 => MatrixCoefficients::Smpte2085,
+            12 => MatrixCoefficients::ChromaDerivedNcl,
+            13 => MatrixCoefficients::ChromaDerivedCl,
+            14 => MatrixCoefficients::ICtCp,
+            _ => MatrixCoefficients::Other(value),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            MatrixCoefficients::Rgb => 0,
+            MatrixCoefficients::Bt709 => 1,
+            MatrixCoefficients::Unspecified => 2,
+            MatrixCoefficients::Fcc => 4,
+            MatrixCoefficients::Bt470Bg => 5,
+            MatrixCoefficients::Smpte170M => 6,
+            MatrixCoefficients::Smpte240M => 7,
+            MatrixCoefficients::YCgCo => 8,
+            MatrixCoefficients::Bt2020Ncl => 9,
+            MatrixCoefficients::Bt2020Cl => 10,
+            MatrixCoefficients::Smpte2085 => 11,
+            MatrixCoefficients::ChromaDerivedNcl => 12,
+            MatrixCoefficients::ChromaDerivedCl => 13,
+            MatrixCoefficients::ICtCp => 14,
+            MatrixCoefficients::Other(value) => value,
+        }
+    }
+
+    fn unknown(self) -> UnknownValueError {
+        UnknownValueError::new("matrix coefficients", self.as_u8())
+    }
+
+    pub fn from_mediainfo(value: &str) -> Result<Self, UnknownValueError> {
+        match value.to_lowercase().as_str() {
+            "rgb" | "identity" => Ok(MatrixCoefficients::Rgb),
+            "bt.709" => Ok(MatrixCoefficients::Bt709),
+            "unspecified" | "unset" => Ok(MatrixCoefficients::Unspecified),
+            "fcc" => Ok(MatrixCoefficients::Fcc),
+            "bt.470 bg" => Ok(MatrixCoefficients::Bt470Bg),
+            "smpte 170m" | "bt.601" => Ok(MatrixCoefficients::Smpte170M),
+            "smpte 240m" => Ok(MatrixCoefficients::Smpte240M),
+            "ycgco" => Ok(MatrixCoefficients::YCgCo),
+            "bt.2020 non-constant" => Ok(MatrixCoefficients::Bt2020Ncl),
+            "bt.2020 constant" => Ok(MatrixCoefficients::Bt2020Cl),
+            "smpte 2085" | "y'd'zd'x" => Ok(MatrixCoefficients::Smpte2085),
+            "chromaticity-derived non-constant" | "chroma-derived non-constant light" => {
+                Ok(MatrixCoefficients::ChromaDerivedNcl)
+            }
+            "chromaticity-derived constant" | "chroma-derived constant light" => {
+                Ok(MatrixCoefficients::ChromaDerivedCl)
+            }
+            "ictcp" => Ok(MatrixCoefficients::ICtCp),
+            _ => Err(UnknownValueError::new("matrix coefficients", value)),
+        }
+    }
+
+    pub fn from_ffmpeg(value: &str) -> Result<Self, UnknownValueError> {
+        match value {
+            "gbr" | "rgb" => Ok(MatrixCoefficients::Rgb),
+            "bt709" => Ok(MatrixCoefficients::Bt709),
+            "unknown" => Ok(MatrixCoefficients::Unspecified),
+            "fcc" => Ok(MatrixCoefficients::Fcc),
+            "bt470bg" => Ok(MatrixCoefficients::Bt470Bg),
+            "smpte170m" => Ok(MatrixCoefficients::Smpte170M),
+            "smpte240m" => Ok(MatrixCoefficients::Smpte240M),
+            "ycgco" => Ok(MatrixCoefficients::YCgCo),
+            "bt2020nc" => Ok(MatrixCoefficients::Bt2020Ncl),
+            "bt2020c" => Ok(MatrixCoefficients::Bt2020Cl),
+            "smpte2085" => Ok(MatrixCoefficients::Smpte2085),
+            "chroma-derived-nc" => Ok(MatrixCoefficients::ChromaDerivedNcl),
+            "chroma-derived-c" => Ok(MatrixCoefficients::ChromaDerivedCl),
+            "ictcp" => Ok(MatrixCoefficients::ICtCp),
+            _ => Err(UnknownValueError::new("matrix coefficients", value)),
+        }
+    }
+
+    pub fn name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("RGB"),
+            MatrixCoefficients::Bt709 => Ok("BT.709"),
+            MatrixCoefficients::Unspecified => Ok("Unspecified"),
+            MatrixCoefficients::Fcc => Ok("FCC"),
+            MatrixCoefficients::Bt470Bg => Ok("BT.470 BG"),
+            MatrixCoefficients::Smpte170M => Ok("SMPTE 170m/BT.601"),
+            MatrixCoefficients::Smpte240M => Ok("SMPTE 240m"),
+            MatrixCoefficients::YCgCo => Ok("YCgCo"),
+            MatrixCoefficients::Bt2020Ncl => Ok("BT.2020 Non-Constant Light"),
+            MatrixCoefficients::Bt2020Cl => Ok("BT.2020 Constant Light"),
+            MatrixCoefficients::Smpte2085 => Ok("SMPTE 2085"),
+            MatrixCoefficients::ChromaDerivedNcl => Ok("Chroma-Derived Non-Constant Light"),
+            MatrixCoefficients::ChromaDerivedCl => Ok("Chroma-Derived Constant Light"),
+            MatrixCoefficients::ICtCp => Ok("ICtCp"),
+            MatrixCoefficients::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    // x265 uses the same names as ffmpeg
+    pub fn x265_name(self) -> Result<&'static str, UnknownValueError> {
+        self.ffmpeg_name()
+    }
+
+    // x264 shares most names with x265, but calls unspecified values "undef"
+    pub fn x264_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("GBR"),
+            MatrixCoefficients::Unspecified => Ok("undef"),
+            MatrixCoefficients::ICtCp => Ok("ICtCp"),
+            _ => self.x265_name(),
+        }
+    }
+
+    pub fn rav1e_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("Identity"),
+            MatrixCoefficients::Bt709 => Ok("BT709"),
+            MatrixCoefficients::Unspecified => Ok("Unspecified"),
+            MatrixCoefficients::Fcc => Ok("FCC"),
+            MatrixCoefficients::Bt470Bg => Ok("BT470BG"),
+            MatrixCoefficients::Smpte170M => Ok("BT601"),
+            MatrixCoefficients::Smpte240M => Ok("SMPTE240"),
+            MatrixCoefficients::YCgCo => Ok("YCgCo"),
+            MatrixCoefficients::Bt2020Ncl => Ok("BT2020NCL"),
+            MatrixCoefficients::Bt2020Cl => Ok("BT2020CL"),
+            MatrixCoefficients::Smpte2085 => Ok("SMPTE2085"),
+            MatrixCoefficients::ChromaDerivedNcl => Ok("ChromatNCL"),
+            MatrixCoefficients::ChromaDerivedCl => Ok("ChromatCL"),
+            MatrixCoefficients::ICtCp => Ok("ICtCp"),
+            MatrixCoefficients::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn aom_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("identity"),
+            MatrixCoefficients::Bt709 => Ok("bt709"),
+            MatrixCoefficients::Unspecified => Ok("unspecified"),
+            MatrixCoefficients::Fcc => Ok("fcc73"),
+            MatrixCoefficients::Bt470Bg => Ok("bt470bg"),
+            MatrixCoefficients::Smpte170M => Ok("bt601"),
+            MatrixCoefficients::Smpte240M => Ok("smpte240"),
+            MatrixCoefficients::YCgCo => Ok("ycgco"),
+            MatrixCoefficients::Bt2020Ncl => Ok("bt2020ncl"),
+            MatrixCoefficients::Bt2020Cl => Ok("bt2020cl"),
+            MatrixCoefficients::Smpte2085 => Ok("smpte2085"),
+            MatrixCoefficients::ChromaDerivedNcl => Ok("chromncl"),
+            MatrixCoefficients::ChromaDerivedCl => Ok("chromcl"),
+            MatrixCoefficients::ICtCp => Ok("ictcp"),
+            MatrixCoefficients::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    pub fn ffmpeg_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("gbr"),
+            MatrixCoefficients::Bt709 => Ok("bt709"),
+            MatrixCoefficients::Unspecified => Ok("unknown"),
+            MatrixCoefficients::Fcc => Ok("fcc"),
+            MatrixCoefficients::Bt470Bg => Ok("bt470bg"),
+            MatrixCoefficients::Smpte170M => Ok("smpte170m"),
+            MatrixCoefficients::Smpte240M => Ok("smpte240m"),
+            MatrixCoefficients::YCgCo => Ok("ycgco"),
+            MatrixCoefficients::Bt2020Ncl => Ok("bt2020nc"),
+            MatrixCoefficients::Bt2020Cl => Ok("bt2020c"),
+            MatrixCoefficients::Smpte2085 => Ok("smpte2085"),
+            MatrixCoefficients::ChromaDerivedNcl => Ok("chroma-derived-nc"),
+            MatrixCoefficients::ChromaDerivedCl => Ok("chroma-derived-c"),
+            MatrixCoefficients::ICtCp => Ok("ictcp"),
+            MatrixCoefficients::Other(_) => Err(self.unknown()),
+        }
+    }
+
+    // zscale's own names where it has them, and otherwise ffmpeg's names.
+    // zimg has no SMPTE 2085 matrix.
+    pub fn zscale_name(self) -> Result<&'static str, UnknownValueError> {
+        match self {
+            MatrixCoefficients::Rgb => Ok("gbr"),
+            MatrixCoefficients::Bt709 => Ok("709"),
+            MatrixCoefficients::Unspecified => Ok("unspecified"),
+            MatrixCoefficients::Fcc => Ok("fcc"),
+            MatrixCoefficients::Bt470Bg => Ok("470bg"),
+            MatrixCoefficients::Smpte170M => Ok("170m"),
+            MatrixCoefficients::Smpte240M => Ok("smpte240m"),
+            MatrixCoefficients::YCgCo => Ok("ycgco"),
+            MatrixCoefficients::Bt2020Ncl => Ok("2020_ncl"),
+            MatrixCoefficients::Bt2020Cl => Ok("2020_cl"),
+            MatrixCoefficients::ChromaDerivedNcl => Ok("chroma-derived-nc"),
+            MatrixCoefficients::ChromaDerivedCl => Ok("chroma-derived-c"),
+            MatrixCoefficients::ICtCp => Ok("ictcp"),
+            MatrixCoefficients::Smpte2085 | MatrixCoefficients::Other(_) => Err(self.unknown()),
+        }
     }
 }
 
-pub fn print_color_primaries(value: u8) -> &'static str {
-    match value {
-        1 => "BT.709",
-        2 => "Unspecified",
-        4 => "BT.470 M",
-        5 => "BT.470 BG",
-        6 => "SMPTE 170m/BT.601",
-        7 => "SMPTE 240m",
-        8 => "Film",
-        9 => "BT.2020",
-        10 => "SMPTE 428",
-        11 => "SMPTE 431.2",
-        12 => "SMPTE 432.1",
-        22 => "EBU 3213 E",
-        _ => panic!("Unrecognized color primaries: {value}"),
+impl From<u8> for MatrixCoefficients {
+    fn from(value: u8) -> Self {
+        MatrixCoefficients::from_u8(value)
     }
 }
 
-pub fn print_x265_color_primaries(value: u8) -> &'static str {
-    match value {
-        1 => "bt709",
-        2 => "unknown",
-        4 => "bt470m",
-        5 => "bt470bg",
-        6 => "smpte170m",
-        7 => "smpte240m",
-        8 => "film",
-        9 => "bt2020",
-        10 => "smpte428",
-        11 => "smpte431",
-        12 => "smpte432",
-        22 => panic!("EBU 3213 E not supported by x265"),
-        _ => panic!("Unrecognized color primaries: {value}"),
+impl From<MatrixCoefficients> for u8 {
+    fn from(value: MatrixCoefficients) -> Self {
+        value.as_u8()
     }
 }
 
-pub fn print_svtav1_color_primaries(value: u8) -> &'static str {
-    match value {
-        1 => "bt709",
-        2 => "unspecified",
-        4 => "bt470m",
-        5 => "bt470bg",
-        6 => "bt601",
-        7 => "smpte240",
-        8 => "film",
-        9 => "bt2020",
-        10 => "xyz",
-        11 => "smpte431",
-        12 => "smpte432",
-        22 => "ebu3213",
-        _ => panic!("Unrecognized color primaries: {value}"),
+impl fmt::Display for MatrixCoefficients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Ok(name) => write!(f, "{}", name),
+            Err(_) => write!(f, "Unknown ({})", self.as_u8()),
+        }
     }
 }
 
-pub fn print_rav1e_color_primaries(value: u8) -> &'static str {
-    match value {
-        1 => "BT709",
-        2 => "Unspecified",
-        4 => "BT470M",
-        5 => "BT470BG",
-        6 => "BT601",
-        7 => "SMPTE240",
-        8 => "GenericFilm",
-        9 => "BT2020",
-        10 => "XYZ",
-        11 => "SMPTE431",
-        12 => "SMPTE432",
-        22 => "EBU3213",
-        // FIXME: The following are rav1e options with an unknown number value
-        // XYZ
-        _ => panic!("Unrecognized color primaries: {value}"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matroska_range_codes() {
+        assert_eq!(ColorRange::from_mkvedit_prop(0), None);
+        assert_eq!(ColorRange::from_mkvedit_prop(1), Some(ColorRange::Limited));
+        assert_eq!(ColorRange::from_mkvedit_prop(2), Some(ColorRange::Full));
+        assert_eq!(ColorRange::from_mkvedit_prop(3), None);
+        assert_eq!(ColorRange::from_mkvedit_prop(4), Some(ColorRange::Other(4)));
+        for range in [ColorRange::Limited, ColorRange::Full, ColorRange::Other(4)] {
+            assert_eq!(
+                ColorRange::from_mkvedit_prop(range.to_mkvedit_prop()),
+                Some(range)
+            );
+        }
+    }
+
+    #[test]
+    fn mediainfo_chromaticity_derived_matrix() {
+        for name in [
+            "Chromaticity-derived non-constant",
+            "Chroma-derived non-constant light",
+        ] {
+            let matrix = MatrixCoefficients::from_mediainfo(name).unwrap();
+            assert_eq!(matrix, MatrixCoefficients::ChromaDerivedNcl);
+            assert_eq!(matrix.as_u8(), 12);
+        }
+        for name in [
+            "Chromaticity-derived constant",
+            "Chroma-derived constant light",
+        ] {
+            let matrix = MatrixCoefficients::from_mediainfo(name).unwrap();
+            assert_eq!(matrix, MatrixCoefficients::ChromaDerivedCl);
+            assert_eq!(matrix.as_u8(), 13);
+        }
+        assert_eq!(
+            MatrixCoefficients::from_ffmpeg("chroma-derived-nc").unwrap(),
+            MatrixCoefficients::ChromaDerivedNcl
+        );
+        assert_eq!(
+            MatrixCoefficients::from_ffmpeg("chroma-derived-c").unwrap(),
+            MatrixCoefficients::ChromaDerivedCl
+        );
+    }
+
+    #[test]
+    fn transfer_names_agree_on_codes() {
+        for code in (1..=18).filter(|&code| code != 3) {
+            let transfer = TransferCharacteristics::from_u8(code);
+            assert_eq!(transfer.as_u8(), code);
+            let name = transfer.ffmpeg_name().unwrap();
+            assert_eq!(
+                TransferCharacteristics::from_ffmpeg(name).unwrap(),
+                transfer,
+                "{}",
+                name
+            );
+            assert_eq!(transfer.x265_name().unwrap(), name);
+            assert!(transfer.name().is_ok());
+            assert!(transfer.rav1e_name().is_ok());
+            assert!(transfer.aom_name().is_ok());
+        }
+
+        let bt1361 = TransferCharacteristics::from_mediainfo("BT.1361").unwrap();
+        assert_eq!(bt1361.as_u8(), 12);
+        assert_eq!(bt1361.x265_name().unwrap(), "bt1361e");
+        assert_eq!(bt1361.x264_name().unwrap(), "bt1361e");
+        assert_eq!(bt1361.rav1e_name().unwrap(), "BT1361");
+
+        for name in ["SMPTE 428", "SMPTE 428M"] {
+            let smpte428 = TransferCharacteristics::from_mediainfo(name).unwrap();
+            assert_eq!(smpte428.as_u8(), 17);
+            assert_eq!(smpte428.x265_name().unwrap(), "smpte428");
+            assert_eq!(smpte428.x264_name().unwrap(), "smpte428");
+            assert_eq!(smpte428.rav1e_name().unwrap(), "SMPTE428");
+        }
+    }
+
+    #[test]
+    fn range_aliases() {
+        for name in ["Limited", "limited", "Limited range", "tv", "TV", "mpeg"] {
+            assert_eq!(
+                ColorRange::from_mediainfo(name).unwrap(),
+                ColorRange::Limited,
+                "{}",
+                name
+            );
+        }
+        for name in ["Full", "Full range", "pc", "PC", "jpeg"] {
+            assert_eq!(
+                ColorRange::from_mediainfo(name).unwrap(),
+                ColorRange::Full,
+                "{}",
+                name
+            );
+        }
+        for name in ["", "range", "studio", "Limited range range"] {
+            assert!(ColorRange::from_mediainfo(name).is_err(), "{}", name);
+        }
+
+        assert_eq!(ColorRange::from_ffmpeg("tv").unwrap(), ColorRange::Limited);
+        assert_eq!(
+            ColorRange::from_ffmpeg("mpeg").unwrap(),
+            ColorRange::Limited
+        );
+        assert_eq!(ColorRange::from_ffmpeg("pc").unwrap(), ColorRange::Full);
+        assert_eq!(ColorRange::from_ffmpeg("jpeg").unwrap(), ColorRange::Full);
+        assert!(ColorRange::from_ffmpeg("unknown").is_err());
     }
 }